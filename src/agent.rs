@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use crate::errors::Result;
 use crate::request::{get, put};
-use crate::Client;
+use crate::{Client, QueryMeta, QueryOptions};
 
 #[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(default)]
@@ -44,6 +44,8 @@ pub struct AgentService {
     pub EnableTagOverride: bool,
     pub CreateIndex: u64,
     pub ModifyIndex: u64,
+    pub Namespace: Option<String>,
+    pub Partition: Option<String>,
 }
 
 //I haven't implemetned https://www.consul.io/api/agent.html#read-configuration
@@ -51,6 +53,10 @@ pub struct AgentService {
 pub trait Agent {
     fn checks(&self) -> Result<HashMap<String, AgentCheck>>;
     fn members(&self, wan: bool) -> Result<AgentMember>;
+    fn services(
+        &self,
+        q: Option<&QueryOptions>,
+    ) -> Result<(HashMap<String, AgentService>, QueryMeta)>;
     fn reload(&self) -> Result<()>;
     fn maintenance_mode(&self, enable: bool, reason: Option<&str>) -> Result<()>;
     fn join(&self, address: &str, wan: bool) -> Result<()>;
@@ -71,6 +77,13 @@ impl Agent for Client {
         }
         get("/v1/agent/members", &self.config, params, None).map(|x| x.0)
     }
+    /// https://www.consul.io/api/agent/service.html#list-services
+    fn services(
+        &self,
+        q: Option<&QueryOptions>,
+    ) -> Result<(HashMap<String, AgentService>, QueryMeta)> {
+        get("/v1/agent/services", &self.config, HashMap::new(), q)
+    }
     /// https://www.consul.io/api/agent.html#reload-agent
     fn reload(&self) -> Result<()> {
         put(
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::agent::{AgentCheck, AgentMember, AgentService};
+use crate::errors::Result;
+use crate::{QueryMeta, QueryOptions};
+
+use super::request::{get, put};
+use super::Client;
+
+#[async_trait]
+pub trait Agent {
+    async fn checks(&self) -> Result<HashMap<String, AgentCheck>>;
+    async fn members(&self, wan: bool) -> Result<AgentMember>;
+    async fn services(
+        &self,
+        q: Option<&QueryOptions>,
+    ) -> Result<(HashMap<String, AgentService>, QueryMeta)>;
+    async fn reload(&self) -> Result<()>;
+    async fn maintenance_mode(&self, enable: bool, reason: Option<&str>) -> Result<()>;
+    async fn join(&self, address: &str, wan: bool) -> Result<()>;
+    async fn leave(&self) -> Result<()>;
+    async fn force_leave(&self) -> Result<()>;
+}
+
+#[async_trait]
+impl Agent for Client {
+    /// https://www.consul.io/api/agent/check.html#list-checks
+    async fn checks(&self) -> Result<HashMap<String, AgentCheck>> {
+        get("/v1/agent/checks", self, HashMap::new(), None)
+            .await
+            .map(|x| x.0)
+    }
+    /// https://www.consul.io/api/agent.html#list-members
+    async fn members(&self, wan: bool) -> Result<AgentMember> {
+        let mut params = HashMap::new();
+        if wan {
+            params.insert(String::from("wan"), String::from("1"));
+        }
+        get("/v1/agent/members", self, params, None)
+            .await
+            .map(|x| x.0)
+    }
+    /// https://www.consul.io/api/agent/service.html#list-services
+    async fn services(
+        &self,
+        q: Option<&QueryOptions>,
+    ) -> Result<(HashMap<String, AgentService>, QueryMeta)> {
+        get("/v1/agent/services", self, HashMap::new(), q).await
+    }
+    /// https://www.consul.io/api/agent.html#reload-agent
+    async fn reload(&self) -> Result<()> {
+        put(
+            "/v1/agent/reload",
+            None as Option<&()>,
+            self,
+            HashMap::new(),
+            None,
+        )
+        .await
+        .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent.html#reload-agent
+    async fn maintenance_mode(&self, enable: bool, reason: Option<&str>) -> Result<()> {
+        let mut params = HashMap::new();
+        let enable_str = if enable {
+            String::from("true")
+        } else {
+            String::from("false")
+        };
+        params.insert(String::from("enabled"), enable_str);
+        if let Some(r) = reason {
+            params.insert(String::from("reason"), r.to_owned());
+        }
+        put(
+            "/v1/agent/maintenance",
+            None as Option<&()>,
+            self,
+            params,
+            None,
+        )
+        .await
+        .map(|x| x.0)
+    }
+    ///https://www.consul.io/api/agent.html#join-agent
+    async fn join(&self, address: &str, wan: bool) -> Result<()> {
+        let mut params = HashMap::new();
+
+        if wan {
+            params.insert(String::from("wan"), String::from("true"));
+        }
+        let path = format!("/v1/agent/join/{}", address);
+        put(&path, None as Option<&()>, self, params, None)
+            .await
+            .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent.html#graceful-leave-and-shutdown
+    async fn leave(&self) -> Result<()> {
+        put(
+            "/v1/agent/leave",
+            None as Option<&()>,
+            self,
+            HashMap::new(),
+            None,
+        )
+        .await
+        .map(|x| x.0)
+    }
+
+    ///https://www.consul.io/api/agent.html#force-leave-and-shutdown
+    async fn force_leave(&self) -> Result<()> {
+        put(
+            "/v1/agent/force-leave",
+            None as Option<&()>,
+            self,
+            HashMap::new(),
+            None,
+        )
+        .await
+        .map(|x| x.0)
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::catalog::{CatalogDeregistration, CatalogRegistration, Node};
+use crate::errors::Result;
+use crate::{QueryMeta, QueryOptions, WriteMeta, WriteOptions};
+
+use super::request::{get, put};
+use super::Client;
+
+#[async_trait]
+pub trait Catalog {
+    async fn register(
+        &self,
+        reg: &CatalogRegistration,
+        q: Option<&WriteOptions>,
+    ) -> Result<((), WriteMeta)>;
+    async fn deregister(
+        &self,
+        dereg: &CatalogDeregistration,
+        q: Option<&WriteOptions>,
+    ) -> Result<((), WriteMeta)>;
+    async fn datacenters(&self) -> Result<(Vec<String>, QueryMeta)>;
+    async fn nodes(&self, q: Option<&QueryOptions>) -> Result<(Vec<Node>, QueryMeta)>;
+    async fn services(
+        &self,
+        q: Option<&QueryOptions>,
+    ) -> Result<(HashMap<String, Vec<String>>, QueryMeta)>;
+}
+
+#[async_trait]
+impl Catalog for Client {
+    /// https://www.consul.io/api/catalog.html#register-entity
+    async fn register(
+        &self,
+        reg: &CatalogRegistration,
+        q: Option<&WriteOptions>,
+    ) -> Result<((), WriteMeta)> {
+        put("/v1/catalog/register", Some(reg), self, HashMap::new(), q).await
+    }
+
+    /// https://www.consul.io/api/catalog.html#deregister-entity
+    async fn deregister(
+        &self,
+        dereg: &CatalogDeregistration,
+        q: Option<&WriteOptions>,
+    ) -> Result<((), WriteMeta)> {
+        put(
+            "/v1/catalog/deregister",
+            Some(dereg),
+            self,
+            HashMap::new(),
+            q,
+        )
+        .await
+    }
+
+    /// https://www.consul.io/api/catalog.html#list-datacenters
+    async fn datacenters(&self) -> Result<(Vec<String>, QueryMeta)> {
+        get("/v1/catalog/datacenters", self, HashMap::new(), None).await
+    }
+
+    /// https://www.consul.io/api/catalog.html#list-nodes
+    async fn nodes(&self, q: Option<&QueryOptions>) -> Result<(Vec<Node>, QueryMeta)> {
+        get("/v1/catalog/nodes", self, HashMap::new(), q).await
+    }
+
+    async fn services(
+        &self,
+        q: Option<&QueryOptions>,
+    ) -> Result<(HashMap<String, Vec<String>>, QueryMeta)> {
+        get("/v1/catalog/services", self, HashMap::new(), q).await
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::connect_ca::{CAConfig, CARootList};
+use crate::errors::Result;
+use crate::{QueryMeta, QueryOptions, WriteMeta, WriteOptions};
+
+use super::request::{get, put};
+use super::Client;
+
+#[allow(clippy::upper_case_acronyms)]
+#[async_trait]
+pub trait ConnectCA {
+    async fn ca_roots(&self, q: Option<&QueryOptions>) -> Result<(CARootList, QueryMeta)>;
+    async fn ca_get_config(&self, q: Option<&QueryOptions>) -> Result<(CAConfig, QueryMeta)>;
+    async fn ca_set_config(
+        &self,
+        conf: &CAConfig,
+        q: Option<&WriteOptions>,
+    ) -> Result<((), WriteMeta)>;
+}
+
+#[async_trait]
+impl ConnectCA for Client {
+    /// https://www.consul.io/api/connect/ca.html#list-ca-root-certificates
+    async fn ca_roots(&self, q: Option<&QueryOptions>) -> Result<(CARootList, QueryMeta)> {
+        get("/v1/connect/ca/roots", self, HashMap::new(), q).await
+    }
+
+    /// https://www.consul.io/api/connect/ca.html#get-ca-configuration
+    async fn ca_get_config(&self, q: Option<&QueryOptions>) -> Result<(CAConfig, QueryMeta)> {
+        get("/v1/connect/ca/configuration", self, HashMap::new(), q).await
+    }
+
+    /// https://www.consul.io/api/connect/ca.html#update-ca-configuration
+    async fn ca_set_config(
+        &self,
+        conf: &CAConfig,
+        q: Option<&WriteOptions>,
+    ) -> Result<((), WriteMeta)> {
+        put(
+            "/v1/connect/ca/configuration",
+            Some(conf),
+            self,
+            HashMap::new(),
+            q,
+        )
+        .await
+    }
+}
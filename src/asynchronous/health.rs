@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::errors::Result;
+use crate::health::ServiceEntry;
+use crate::{QueryMeta, QueryOptions};
+
+use super::request::get;
+use super::Client;
+
+#[async_trait]
+pub trait Health {
+    async fn service(
+        &self,
+        service: &str,
+        tag: Option<&str>,
+        passing_only: bool,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<ServiceEntry>, QueryMeta)>;
+}
+
+#[async_trait]
+impl Health for Client {
+    async fn service(
+        &self,
+        service: &str,
+        tag: Option<&str>,
+        passing_only: bool,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<ServiceEntry>, QueryMeta)> {
+        let mut params = HashMap::new();
+        let path = format!("/v1/health/service/{}", service);
+        if passing_only {
+            params.insert(String::from("passing"), String::from("1"));
+        }
+        if let Some(tag) = tag {
+            params.insert(String::from("tag"), tag.to_owned());
+        }
+        get(&path, self, params, options).await
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::errors::{Error, ErrorKind, Result};
+use crate::kv::{
+    cas_params, cas_result, delete_cas_params, delete_txn, delete_txn_result, keys_params,
+    read_options, typed_pair, update_pair, Codec, DeleteOptions, KVPair, TypedKVPair,
+};
+use crate::{QueryMeta, QueryOptions, WriteMeta, WriteOptions};
+
+use super::request::{delete, get_raw, get_vec, put_raw};
+use super::txn::Transaction;
+use super::Client;
+
+#[allow(clippy::upper_case_acronyms)]
+#[async_trait]
+pub trait KV {
+    async fn acquire(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    async fn delete(&self, _: &str, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    async fn delete_cas(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<WriteMeta>;
+    async fn delete_with(
+        &self,
+        _: &str,
+        _: &DeleteOptions,
+        _: Option<&WriteOptions>,
+    ) -> Result<(bool, WriteMeta)>;
+    async fn get(&self, _: &str, _: Option<&QueryOptions>) -> Result<(Option<KVPair>, QueryMeta)>;
+    async fn get_typed<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        codec: Codec,
+        o: Option<&QueryOptions>,
+    ) -> Result<(Option<TypedKVPair<T>>, QueryMeta)>
+    where
+        Self: Sized;
+    async fn get_raw(
+        &self,
+        _: &str,
+        _: Option<&QueryOptions>,
+    ) -> Result<(Option<Vec<u8>>, QueryMeta)>;
+    async fn keys(
+        &self,
+        prefix: &str,
+        separator: Option<&str>,
+        _: Option<&QueryOptions>,
+    ) -> Result<(Vec<String>, QueryMeta)>;
+    async fn list(&self, _: &str, _: Option<&QueryOptions>) -> Result<(Vec<KVPair>, QueryMeta)>;
+    async fn put(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    async fn put_cas(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<WriteMeta>;
+    async fn put_typed<T: Serialize + Sync + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        codec: Codec,
+        cas: Option<u64>,
+        o: Option<&WriteOptions>,
+    ) -> Result<WriteMeta>
+    where
+        Self: Sized;
+    async fn release(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    async fn update<F>(
+        &self,
+        key: &str,
+        max_attempts: u32,
+        f: F,
+        o: Option<&WriteOptions>,
+    ) -> Result<(Vec<u8>, WriteMeta)>
+    where
+        F: FnMut(Option<&KVPair>) -> Result<Vec<u8>> + Send,
+        Self: Sized;
+}
+
+#[async_trait]
+impl KV for Client {
+    async fn acquire(&self, pair: &KVPair, o: Option<&WriteOptions>) -> Result<(bool, WriteMeta)> {
+        let mut params = HashMap::new();
+        if let Some(i) = pair.Flags {
+            if i != 0 {
+                params.insert(String::from("flags"), i.to_string());
+            }
+        }
+        if let Some(ref session) = pair.Session {
+            params.insert(String::from("acquire"), session.to_owned());
+            let path = format!("/v1/kv/{}", pair.Key);
+            put_raw(&path, &pair.Value, self, params, o).await
+        } else {
+            Err(Error::from("Session flag is required to acquire lock"))
+        }
+    }
+
+    async fn delete(&self, key: &str, options: Option<&WriteOptions>) -> Result<(bool, WriteMeta)> {
+        let path = format!("/v1/kv/{}", key);
+        delete(&path, self, HashMap::new(), options).await
+    }
+
+    async fn delete_cas(&self, pair: &KVPair, options: Option<&WriteOptions>) -> Result<WriteMeta> {
+        let params = delete_cas_params(pair)?;
+        let path = format!("/v1/kv/{}", pair.Key);
+        let (deleted, meta) = delete(&path, self, params, options).await?;
+        cas_result(&pair.Key, deleted, meta)
+    }
+
+    async fn delete_with(
+        &self,
+        key: &str,
+        options: &DeleteOptions,
+        o: Option<&WriteOptions>,
+    ) -> Result<(bool, WriteMeta)> {
+        let txn = delete_txn(key, options)?;
+        delete_txn_result(key, options, self.txn(&txn, o).await)
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Option<KVPair>, QueryMeta)> {
+        let path = format!("/v1/kv/{}", key);
+        let x: Result<(Vec<KVPair>, QueryMeta)> =
+            get_vec(&path, self, HashMap::new(), options).await;
+        x.map(|r| (r.0.first().cloned(), r.1))
+    }
+
+    async fn get_typed<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        codec: Codec,
+        o: Option<&QueryOptions>,
+    ) -> Result<(Option<TypedKVPair<T>>, QueryMeta)> {
+        let (pair, meta) = self.get(key, o).await?;
+        Ok((pair.map(|p| typed_pair(p, codec)).transpose()?, meta))
+    }
+
+    async fn get_raw(
+        &self,
+        key: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Option<Vec<u8>>, QueryMeta)> {
+        let mut params = HashMap::new();
+        params.insert(String::from("raw"), String::new());
+        let path = format!("/v1/kv/{}", key);
+        get_raw(&path, self, params, options).await
+    }
+
+    async fn keys(
+        &self,
+        prefix: &str,
+        separator: Option<&str>,
+        o: Option<&QueryOptions>,
+    ) -> Result<(Vec<String>, QueryMeta)> {
+        let path = format!("/v1/kv/{}", prefix);
+        get_vec(&path, self, keys_params(separator), o).await
+    }
+
+    async fn list(
+        &self,
+        prefix: &str,
+        o: Option<&QueryOptions>,
+    ) -> Result<(Vec<KVPair>, QueryMeta)> {
+        let mut params = HashMap::new();
+        params.insert(String::from("recurse"), String::from(""));
+        let path = format!("/v1/kv/{}", prefix);
+        get_vec(&path, self, params, o).await
+    }
+
+    async fn put(&self, pair: &KVPair, o: Option<&WriteOptions>) -> Result<(bool, WriteMeta)> {
+        let mut params = HashMap::new();
+        if let Some(i) = pair.Flags {
+            if i != 0 {
+                params.insert(String::from("flags"), i.to_string());
+            }
+        }
+        let path = format!("/v1/kv/{}", pair.Key);
+        put_raw(&path, &pair.Value, self, params, o).await
+    }
+
+    async fn put_cas(&self, pair: &KVPair, o: Option<&WriteOptions>) -> Result<WriteMeta> {
+        let path = format!("/v1/kv/{}", pair.Key);
+        let (written, meta) = put_raw(&path, &pair.Value, self, cas_params(pair), o).await?;
+        cas_result(&pair.Key, written, meta)
+    }
+
+    async fn put_typed<T: Serialize + Sync + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        codec: Codec,
+        cas: Option<u64>,
+        o: Option<&WriteOptions>,
+    ) -> Result<WriteMeta> {
+        let pair = KVPair {
+            Key: key.to_owned(),
+            Value: codec.encode(key, value)?,
+            ModifyIndex: cas,
+            ..Default::default()
+        };
+        match cas {
+            Some(_) => self.put_cas(&pair, o).await,
+            None => self.put(&pair, o).await.map(|(_, meta)| meta),
+        }
+    }
+
+    async fn release(&self, pair: &KVPair, o: Option<&WriteOptions>) -> Result<(bool, WriteMeta)> {
+        let mut params = HashMap::new();
+        if let Some(i) = pair.Flags {
+            if i != 0 {
+                params.insert(String::from("flags"), i.to_string());
+            }
+        }
+        if let Some(ref session) = pair.Session {
+            params.insert(String::from("release"), session.to_owned());
+            let path = format!("/v1/kv/{}", pair.Key);
+            put_raw(&path, &pair.Value, self, params, o).await
+        } else {
+            Err(Error::from("Session flag is required to release a lock"))
+        }
+    }
+
+    async fn update<F>(
+        &self,
+        key: &str,
+        max_attempts: u32,
+        mut f: F,
+        o: Option<&WriteOptions>,
+    ) -> Result<(Vec<u8>, WriteMeta)>
+    where
+        F: FnMut(Option<&KVPair>) -> Result<Vec<u8>> + Send,
+    {
+        let query = read_options(o);
+        let mut attempt = 1;
+        loop {
+            let (current, _) = self.get(key, Some(&query)).await?;
+            let value = f(current.as_ref())?;
+            let pair = update_pair(key, current, value);
+            match self.put_cas(&pair, o).await {
+                Err(Error(ErrorKind::CasLost(_), _)) if attempt < max_attempts => attempt += 1,
+                result => return result.map(|meta| (pair.Value, meta)),
+            }
+        }
+    }
+}
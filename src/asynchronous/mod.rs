@@ -0,0 +1,37 @@
+//! Async counterparts of the `Client` and of the API traits, available with
+//! the `async` cargo feature.
+//!
+//! They share the models, the `Config` and the `QueryOptions`/`QueryMeta`
+//! handling of the blocking API, so both can be used from the same crate.
+
+pub mod agent;
+pub mod catalog;
+pub mod connect_ca;
+pub mod health;
+pub mod kv;
+pub mod session;
+pub mod txn;
+
+mod request;
+
+use std::sync::Arc;
+
+use crate::interceptor::Interceptor;
+use crate::Config;
+
+#[derive(Clone, Debug)]
+pub struct Client {
+    config: Config,
+}
+
+impl Client {
+    pub fn new(config: Config) -> Self {
+        Client { config }
+    }
+
+    /// Adds an interceptor called around each request of this client.
+    pub fn with_interceptor(mut self, interceptor: Arc<dyn Interceptor>) -> Self {
+        self.config.interceptors.push(interceptor);
+        self
+    }
+}
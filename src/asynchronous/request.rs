@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::errors::{ErrorKind, Result, ResultExt};
+use crate::interceptor::{RequestInfo, ResponseInfo};
+use crate::request::{
+    agent_order, agent_url, is_connect_error, is_transient_error, query_request, select_agent,
+    write_request, Body, Response,
+};
+use crate::transport::{AsyncTransport, HttpRequest, HttpResponse};
+#[cfg(unix)]
+use crate::unix;
+use crate::{Config, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
+
+use super::Client;
+
+/// Sends a request to the given agent through the `AsyncTransport` of the
+/// `Config`, or by default over HTTP, or over the agent Unix domain socket
+/// when its address uses the `unix://` scheme.
+async fn send_to(config: &Config, address: &str, request: HttpRequest) -> Result<HttpResponse> {
+    if let Some(ref transport) = config.async_transport {
+        return transport.send(request).await;
+    }
+    #[cfg(unix)]
+    {
+        if let Some(path) = unix::socket_path(address) {
+            return unix::send_async(path, &request)
+                .await
+                .chain_err(|| ErrorKind::Transport);
+        }
+    }
+    AsyncTransport::send(&config.async_http_client, request).await
+}
+
+/// Sends a request to the active agent, failing over to the next ones when it
+/// cannot be connected to. Returns the response and the agent address.
+async fn send(config: &Config, request: &HttpRequest) -> Result<(HttpResponse, String)> {
+    let mut primary_failed = false;
+    let mut error = None;
+    for index in agent_order(config) {
+        let (address, url) = agent_url(config, index, &request.url)?;
+        let mut request = request.clone();
+        request.url = url;
+        match send_to(config, address, request).await {
+            Ok(response) => {
+                select_agent(config, index, primary_failed);
+                return Ok((response, address.to_owned()));
+            }
+            Err(e) if is_connect_error(&e) => {
+                primary_failed |= index == 0;
+                error = Some(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(error.expect("at least one agent is tried"))
+}
+
+/// Sends the request, retrying it according to the `RetryPolicy` of the
+/// `Config` when `idempotent` is set, and calls the interceptors of the
+/// `Client` around it.
+async fn execute(config: &Config, idempotent: bool, request: HttpRequest) -> Result<Response> {
+    let info = if config.interceptors.is_empty() {
+        None
+    } else {
+        Some(RequestInfo::new(&request))
+    };
+    let mut contexts: Vec<_> = match info {
+        Some(ref info) => config
+            .interceptors
+            .iter()
+            .map(|interceptor| interceptor.on_request(info))
+            .collect(),
+        None => Vec::new(),
+    };
+    let start = Instant::now();
+    let policy = &config.retry;
+    let mut attempts = 1;
+    loop {
+        let result = send(config, &request)
+            .await
+            .map(|r| Response::new(r, attempts));
+        let retry = match &result {
+            Ok(r) => policy.should_retry_status(r.status.as_u16()),
+            Err(e) => is_transient_error(e),
+        };
+        if !idempotent || !retry || attempts >= policy.max_attempts {
+            if let Some(ref info) = info {
+                let response = ResponseInfo::new(&result, attempts, start.elapsed());
+                let contexts = contexts.drain(..);
+                for (interceptor, context) in config.interceptors.iter().zip(contexts) {
+                    interceptor.on_response(info, &response, context);
+                }
+            }
+            return result;
+        }
+        tokio::time::sleep(policy.backoff(attempts)).await;
+        attempts += 1;
+    }
+}
+
+pub async fn get_vec<R: DeserializeOwned>(
+    path: &str,
+    client: &Client,
+    params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<(Vec<R>, QueryMeta)> {
+    let config = &client.config;
+    let request = query_request(config, path, params, options)?;
+    let start = Instant::now();
+    execute(config, true, request).await?.into_query_vec(start)
+}
+
+pub async fn get<R: DeserializeOwned>(
+    path: &str,
+    client: &Client,
+    params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<(R, QueryMeta)> {
+    let config = &client.config;
+    let request = query_request(config, path, params, options)?;
+    let start = Instant::now();
+    execute(config, true, request).await?.into_query(start)
+}
+
+/// Reads the raw body of a response, e.g. of the `?raw` KV reads.
+pub async fn get_raw(
+    path: &str,
+    client: &Client,
+    params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<(Option<Vec<u8>>, QueryMeta)> {
+    let config = &client.config;
+    let request = query_request(config, path, params, options)?;
+    let start = Instant::now();
+    execute(config, true, request).await?.into_raw(start)
+}
+
+pub async fn delete<R: DeserializeOwned>(
+    path: &str,
+    client: &Client,
+    params: HashMap<String, String>,
+    options: Option<&WriteOptions>,
+) -> Result<(R, WriteMeta)> {
+    write_with_body(Method::DELETE, path, None, client, params, options).await
+}
+
+pub async fn put<T: Serialize, R: DeserializeOwned>(
+    path: &str,
+    body: Option<&T>,
+    client: &Client,
+    params: HashMap<String, String>,
+    options: Option<&WriteOptions>,
+) -> Result<(R, WriteMeta)> {
+    let body = body.map(Body::json).transpose()?;
+    write_with_body(Method::PUT, path, body, client, params, options).await
+}
+
+/// Writes the body as is, e.g. a KV value.
+pub async fn put_raw<R: DeserializeOwned>(
+    path: &str,
+    body: &[u8],
+    client: &Client,
+    params: HashMap<String, String>,
+    options: Option<&WriteOptions>,
+) -> Result<(R, WriteMeta)> {
+    let body = Some(Body::raw(body));
+    write_with_body(Method::PUT, path, body, client, params, options).await
+}
+
+async fn write_with_body<R: DeserializeOwned>(
+    method: Method,
+    path: &str,
+    body: Option<Body>,
+    client: &Client,
+    params: HashMap<String, String>,
+    options: Option<&WriteOptions>,
+) -> Result<(R, WriteMeta)> {
+    let config = &client.config;
+    let start = Instant::now();
+    let request = write_request(config, method, path, body, params, options)?;
+    let retry = options.map(|o| o.retry).unwrap_or(false);
+    execute(config, retry, request).await?.into_write(start)
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::errors::Result;
+use crate::session::SessionEntry;
+use crate::{QueryMeta, QueryOptions, WriteMeta, WriteOptions};
+
+use super::request::{get, put};
+use super::Client;
+
+#[async_trait]
+pub trait Session {
+    async fn create(
+        &self,
+        session: &SessionEntry,
+        options: Option<&WriteOptions>,
+    ) -> Result<(SessionEntry, WriteMeta)>;
+    async fn destroy(&self, id: &str, options: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    async fn info(
+        &self,
+        id: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<SessionEntry>, QueryMeta)>;
+    async fn list(&self, options: Option<&QueryOptions>) -> Result<(Vec<SessionEntry>, QueryMeta)>;
+    async fn node(
+        &self,
+        node: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<SessionEntry>, QueryMeta)>;
+    async fn renew(
+        &self,
+        id: &str,
+        options: Option<&WriteOptions>,
+    ) -> Result<(Vec<SessionEntry>, WriteMeta)>;
+}
+
+#[async_trait]
+impl Session for Client {
+    async fn create(
+        &self,
+        session: &SessionEntry,
+        options: Option<&WriteOptions>,
+    ) -> Result<(SessionEntry, WriteMeta)> {
+        put(
+            "/v1/session/create",
+            Some(session),
+            self,
+            HashMap::new(),
+            options,
+        )
+        .await
+    }
+    async fn destroy(&self, id: &str, options: Option<&WriteOptions>) -> Result<(bool, WriteMeta)> {
+        let path = format!("/v1/session/destroy/{}", id);
+        put(&path, None as Option<&()>, self, HashMap::new(), options).await
+    }
+    async fn info(
+        &self,
+        id: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<SessionEntry>, QueryMeta)> {
+        let path = format!("/v1/session/info/{}", id);
+        get(&path, self, HashMap::new(), options).await
+    }
+    async fn list(&self, options: Option<&QueryOptions>) -> Result<(Vec<SessionEntry>, QueryMeta)> {
+        get("/v1/session/list", self, HashMap::new(), options).await
+    }
+    async fn node(
+        &self,
+        node: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<SessionEntry>, QueryMeta)> {
+        let path = format!("/v1/session/node/{}", node);
+        get(&path, self, HashMap::new(), options).await
+    }
+
+    async fn renew(
+        &self,
+        id: &str,
+        options: Option<&WriteOptions>,
+    ) -> Result<(Vec<SessionEntry>, WriteMeta)> {
+        let path = format!("/v1/session/renew/{}", id);
+        put(&path, None as Option<&()>, self, HashMap::new(), options).await
+    }
+}
@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::errors::Result;
+use crate::txn::{txn_result, Txn, TxnResult};
+use crate::{WriteMeta, WriteOptions};
+
+use super::request::put;
+use super::Client;
+
+#[async_trait]
+pub trait Transaction {
+    async fn txn(&self, txn: &Txn, o: Option<&WriteOptions>)
+        -> Result<(Vec<TxnResult>, WriteMeta)>;
+}
+
+#[async_trait]
+impl Transaction for Client {
+    async fn txn(
+        &self,
+        txn: &Txn,
+        o: Option<&WriteOptions>,
+    ) -> Result<(Vec<TxnResult>, WriteMeta)> {
+        let result = put("/v1/txn", Some(&txn.ops), self, HashMap::new(), o).await;
+        txn_result(result)
+    }
+}
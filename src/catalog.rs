@@ -104,7 +104,7 @@ impl Catalog for Client {
         q: Option<&WriteOptions>,
     ) -> Result<((), WriteMeta)> {
         put(
-            "/v1/session/create",
+            "/v1/catalog/register",
             Some(reg),
             &self.config,
             HashMap::new(),
@@ -0,0 +1,319 @@
+//! Leader election over a KV key, campaigning in the background with a
+//! session and `KV::acquire` as in the Consul leader election recipe.
+//!
+//! The key has the flags of the `lock` module, so that a `Lock` and an
+//! election over the same key exclude each other.
+//!
+//! ```no_run
+//! use consul::election::{ElectionOptions, LeaderElection};
+//! use consul::{Client, Config};
+//!
+//! let client = Client::new(Config::new().unwrap());
+//! let options = ElectionOptions {
+//!     value: b"host-1".to_vec(),
+//!     ..ElectionOptions::new("service/batch/leader")
+//! };
+//! let mut election = LeaderElection::new(client, options)
+//!     .on_elected(|| println!("elected"))
+//!     .on_deposed(|| println!("deposed"));
+//! election.campaign().unwrap();
+//! if election.is_leader() {
+//!     // Work as the leader
+//! }
+//! println!("leader: {:?}", election.leader().unwrap());
+//! election.resign().unwrap();
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::errors::Result;
+use crate::kv::{KVPair, KV};
+use crate::lock::{blocking_wait, SessionKeeper, LOCK_FLAG_VALUE};
+use crate::watch::StopHandle;
+use crate::{Client, QueryOptions};
+
+#[derive(Clone, Debug)]
+pub struct ElectionOptions {
+    pub key: String,
+    /// Value of the key while leading, read by the other candidates.
+    pub value: Vec<u8>,
+    /// Name and TTL of the sessions created to campaign, renewed while
+    /// campaigning and replaced when invalidated.
+    pub session_name: String,
+    pub session_ttl: Duration,
+    /// Maximum duration of each blocking query watching the key.
+    pub wait_time: Duration,
+    /// Delay before retrying after a failed query, or to acquire a free key
+    /// during the lock-delay following the invalidation of its last holder.
+    pub retry_time: Duration,
+}
+
+impl Default for ElectionOptions {
+    fn default() -> Self {
+        ElectionOptions {
+            key: String::new(),
+            value: Vec::new(),
+            session_name: String::from("Consul API Leader Election"),
+            session_ttl: Duration::from_secs(15),
+            wait_time: Duration::from_secs(15),
+            retry_time: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ElectionOptions {
+    pub fn new(key: &str) -> ElectionOptions {
+        ElectionOptions {
+            key: key.to_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+type Callback = Arc<dyn Fn() + Send + Sync>;
+
+/// State shared with the campaign thread.
+struct Shared {
+    leader: AtomicBool,
+    /// Serializes the leadership changes, not their callbacks, which may
+    /// resign.
+    transition: Mutex<()>,
+    on_elected: Option<Callback>,
+    on_deposed: Option<Callback>,
+}
+
+impl Shared {
+    /// Records whether the session leads, then calls the callback of the
+    /// change if any. No longer elects once the campaign is stopped.
+    fn transition(&self, leader: bool, stop: &StopHandle) {
+        let changed = {
+            let _guard = self.transition.lock().unwrap_or_else(|e| e.into_inner());
+            if leader && stop.is_stopped() {
+                return;
+            }
+            self.leader.swap(leader, Ordering::SeqCst) != leader
+        };
+        let callback = match (changed, leader) {
+            (false, _) => return,
+            (true, true) => &self.on_elected,
+            (true, false) => &self.on_deposed,
+        };
+        if let Some(callback) = callback {
+            callback();
+        }
+    }
+}
+
+struct Campaign {
+    shared: Arc<Shared>,
+    keeper: Arc<Mutex<Option<SessionKeeper>>>,
+    stop: StopHandle,
+}
+
+fn lock_keeper(keeper: &Mutex<Option<SessionKeeper>>) -> MutexGuard<'_, Option<SessionKeeper>> {
+    keeper.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A candidate of the leader election over a key, campaigning until it
+/// resigns or is dropped.
+pub struct LeaderElection {
+    client: Client,
+    options: ElectionOptions,
+    on_elected: Option<Callback>,
+    on_deposed: Option<Callback>,
+    campaign: Option<Campaign>,
+}
+
+impl LeaderElection {
+    pub fn new(client: Client, options: ElectionOptions) -> LeaderElection {
+        LeaderElection {
+            client,
+            options,
+            on_elected: None,
+            on_deposed: None,
+            campaign: None,
+        }
+    }
+
+    /// Called from the campaign thread when this candidate becomes the
+    /// leader.
+    pub fn on_elected<F: Fn() + Send + Sync + 'static>(mut self, callback: F) -> Self {
+        self.on_elected = Some(Arc::new(callback));
+        self
+    }
+
+    /// Called when this candidate stops being the leader, because its
+    /// session was invalidated, its key was taken over or it resigned.
+    pub fn on_deposed<F: Fn() + Send + Sync + 'static>(mut self, callback: F) -> Self {
+        self.on_deposed = Some(Arc::new(callback));
+        self
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.campaign
+            .as_ref()
+            .is_some_and(|c| c.shared.leader.load(Ordering::SeqCst))
+    }
+
+    /// Value of the key set by the current leader, `None` when there is no
+    /// leader.
+    pub fn leader(&self) -> Result<Option<Vec<u8>>> {
+        let (pair, _) = self.client.get(&self.options.key, None)?;
+        Ok(pair.filter(|p| p.Session.is_some()).map(|p| p.Value))
+    }
+
+    /// Starts campaigning in the background, unless already campaigning.
+    /// Fails if the key is used for something else than an election or a
+    /// lock.
+    pub fn campaign(&mut self) -> Result<()> {
+        if self.campaign.is_some() {
+            return Ok(());
+        }
+        if let (Some(pair), _) = self.client.get(&self.options.key, None)? {
+            if pair.Flags != Some(LOCK_FLAG_VALUE) {
+                bail!("The key '{}' is not used as a lock", self.options.key);
+            }
+        }
+        let campaign = Campaign {
+            shared: Arc::new(Shared {
+                leader: AtomicBool::new(false),
+                transition: Mutex::new(()),
+                on_elected: self.on_elected.clone(),
+                on_deposed: self.on_deposed.clone(),
+            }),
+            keeper: Arc::new(Mutex::new(None)),
+            stop: StopHandle::default(),
+        };
+        {
+            let (client, options) = (self.client.clone(), self.options.clone());
+            let (shared, keeper) = (campaign.shared.clone(), campaign.keeper.clone());
+            let stop = campaign.stop.clone();
+            thread::spawn(move || run(&client, &options, &shared, &keeper, &stop));
+        }
+        self.campaign = Some(campaign);
+        Ok(())
+    }
+
+    /// Stops campaigning, stepping down if leading. The session of the
+    /// campaign is destroyed, and the election can be campaigned again.
+    pub fn resign(&mut self) -> Result<()> {
+        let campaign = match self.campaign.take() {
+            Some(campaign) => campaign,
+            None => return Ok(()),
+        };
+        campaign.stop.stop();
+        let keeper = lock_keeper(&campaign.keeper).take();
+        let result = match keeper {
+            Some(keeper) => {
+                let pair = KVPair {
+                    Key: self.options.key.clone(),
+                    Value: self.options.value.clone(),
+                    Flags: Some(LOCK_FLAG_VALUE),
+                    Session: Some(keeper.id().to_owned()),
+                    ..Default::default()
+                };
+                let released = self.client.release(&pair, None).map(|_| ());
+                released.and(keeper.destroy())
+            }
+            None => Ok(()),
+        };
+        campaign.shared.transition(false, &campaign.stop);
+        result
+    }
+}
+
+impl Drop for LeaderElection {
+    fn drop(&mut self) {
+        let _ = self.resign();
+    }
+}
+
+/// Campaign loop: watches the key, acquiring it whenever it is free and
+/// replacing the session when Consul refuses it.
+fn run(
+    client: &Client,
+    options: &ElectionOptions,
+    shared: &Shared,
+    keeper: &Mutex<Option<SessionKeeper>>,
+    stop: &StopHandle,
+) {
+    let mut wait_index = None;
+    while !stop.is_stopped() {
+        let session = {
+            // Checked under the lock so that no session outlives a resign
+            let mut keeper = lock_keeper(keeper);
+            if stop.is_stopped() {
+                break;
+            }
+            match &*keeper {
+                Some(keeper) => keeper.id().to_owned(),
+                None => {
+                    let created =
+                        SessionKeeper::create(client, &options.session_name, options.session_ttl);
+                    match created {
+                        Ok(created) => keeper.insert(created).id().to_owned(),
+                        Err(_) => {
+                            drop(keeper);
+                            stop.sleep(options.retry_time);
+                            continue;
+                        }
+                    }
+                }
+            }
+        };
+
+        let query = QueryOptions {
+            wait_index,
+            wait_time: Some(blocking_wait(options.wait_time)),
+            ..Default::default()
+        };
+        let pair = match client.get(&options.key, Some(&query)) {
+            Ok((pair, meta)) => {
+                wait_index = meta.last_index;
+                pair
+            }
+            Err(_) => {
+                // Leadership can no longer be vouched for
+                shared.transition(false, stop);
+                wait_index = None;
+                stop.sleep(options.retry_time);
+                continue;
+            }
+        };
+        if let Some(pair) = &pair {
+            let holder = pair.Session.as_deref();
+            shared.transition(holder == Some(session.as_str()), stop);
+            if holder.is_some() || pair.Flags != Some(LOCK_FLAG_VALUE) {
+                continue;
+            }
+        } else {
+            shared.transition(false, stop);
+        }
+
+        let pair = KVPair {
+            Key: options.key.clone(),
+            Value: options.value.clone(),
+            Flags: Some(LOCK_FLAG_VALUE),
+            Session: Some(session),
+            ..Default::default()
+        };
+        match client.acquire(&pair, None) {
+            Ok((true, _)) => {}
+            // Lost the race or within the lock-delay
+            Ok((false, _)) => {
+                stop.sleep(options.retry_time);
+            }
+            // The session was invalidated, campaign with a new one
+            Err(_) => {
+                if let Some(keeper) = lock_keeper(keeper).take() {
+                    let _ = keeper.destroy();
+                }
+                stop.sleep(options.retry_time);
+            }
+        }
+        wait_index = None;
+    }
+}
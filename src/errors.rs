@@ -1,10 +1,46 @@
+#![allow(unexpected_cfgs)]
+
 error_chain! {
     errors{
-        BadUrl{
-            description("")
+        BadUrl(url: String) {
+            description("invalid Consul URL")
+            display("Failed to parse URL: '{}'", url)
+        }
+        Transport {
+            description("HTTP request to consul failed")
+            display("HTTP request to consul failed")
+        }
+        ConsulError(status: u16, body: String) {
+            description("consul returned an error status")
+            display("Consul returned status {}: {}", status, body)
+        }
+        PermissionDenied(body: String) {
+            description("permission denied by consul")
+            display("Permission denied: {}", body)
+        }
+        NotFound(body: String) {
+            description("resource not found in consul")
+            display("Not found: {}", body)
+        }
+        CasLost(key: String) {
+            description("check-and-set write lost against a concurrent change")
+            display("Check-and-set of '{}' lost against a concurrent change", key)
+        }
+        TxnRolledBack(errors: Vec<crate::txn::TxnError>) {
+            description("transaction rolled back by consul")
+            display("Transaction rolled back: {}", errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", "))
+        }
+        ValueEncode(key: String, codec: String) {
+            description("failed to encode a KV value")
+            display("Failed to encode the value of '{}' as {}", key, codec)
+        }
+        ValueDecode(key: String, codec: String) {
+            description("failed to decode a KV value")
+            display("Failed to decode the value of '{}' as {}", key, codec)
         }
-        ConsulError{
-            description("")
+        Decode {
+            description("failed to decode consul response")
+            display("Failed to parse JSON response")
         }
     }
 
@@ -0,0 +1,254 @@
+//! Typed builder for the filter expressions of Consul's `filter` query
+//! parameter.
+//!
+//! ```
+//! use consul::filter::Selector;
+//!
+//! let filter = Selector::contains("Service.Tags", "canary")
+//!     .and(Selector::eq("Service.Meta.version", "2").or(Selector::is_empty("Checks")));
+//! assert_eq!(
+//!     filter.to_expression().unwrap(),
+//!     r#"Service.Tags contains "canary" and (Service.Meta.version == "2" or Checks is empty)"#
+//! );
+//! ```
+
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::errors::Result;
+
+/// Comparison applied by a `Filter::Match` to its selector.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MatchOperator {
+    Equal,
+    NotEqual,
+    IsEmpty,
+    IsNotEmpty,
+    Contains,
+    NotContains,
+    Matches,
+    NotMatches,
+}
+
+/// A filter expression, rendered in the bexpr syntax Consul expects.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Filter {
+    Match {
+        selector: String,
+        operator: MatchOperator,
+        value: Option<String>,
+    },
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+}
+
+/// Entry points of the filter builder, each matching a selector such as
+/// `Service.Tags` or `NodeMeta.env`.
+pub struct Selector;
+
+impl Selector {
+    /// Selector of an entry of a map, for keys that are not identifiers, e.g.
+    /// `Selector::index("Service.Meta", "my-key")` for `Service.Meta["my-key"]`.
+    pub fn index(selector: &str, key: &str) -> String {
+        let mut out = format!("{}[", selector);
+        quote(key, &mut out);
+        out.push(']');
+        out
+    }
+
+    fn filter(selector: &str, operator: MatchOperator, value: Option<&str>) -> Filter {
+        Filter::Match {
+            selector: selector.to_owned(),
+            operator,
+            value: value.map(String::from),
+        }
+    }
+
+    pub fn eq(selector: &str, value: &str) -> Filter {
+        Selector::filter(selector, MatchOperator::Equal, Some(value))
+    }
+
+    pub fn ne(selector: &str, value: &str) -> Filter {
+        Selector::filter(selector, MatchOperator::NotEqual, Some(value))
+    }
+
+    pub fn is_empty(selector: &str) -> Filter {
+        Selector::filter(selector, MatchOperator::IsEmpty, None)
+    }
+
+    pub fn is_not_empty(selector: &str) -> Filter {
+        Selector::filter(selector, MatchOperator::IsNotEmpty, None)
+    }
+
+    /// Matches lists, maps and strings containing the value.
+    pub fn contains(selector: &str, value: &str) -> Filter {
+        Selector::filter(selector, MatchOperator::Contains, Some(value))
+    }
+
+    pub fn not_contains(selector: &str, value: &str) -> Filter {
+        Selector::filter(selector, MatchOperator::NotContains, Some(value))
+    }
+
+    /// Matches strings against a regular expression.
+    pub fn matches(selector: &str, regex: &str) -> Filter {
+        Selector::filter(selector, MatchOperator::Matches, Some(regex))
+    }
+
+    pub fn not_matches(selector: &str, regex: &str) -> Filter {
+        Selector::filter(selector, MatchOperator::NotMatches, Some(regex))
+    }
+}
+
+impl Filter {
+    pub fn and(self, other: Filter) -> Filter {
+        Filter::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Filter) -> Filter {
+        Filter::Or(Box::new(self), Box::new(other))
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Filter {
+        Filter::Not(Box::new(self))
+    }
+
+    /// Renders the expression, failing on malformed selectors.
+    pub fn to_expression(&self) -> Result<String> {
+        let mut expression = String::new();
+        self.render(&mut expression)?;
+        Ok(expression)
+    }
+
+    fn render(&self, out: &mut String) -> Result<()> {
+        match self {
+            Filter::Match {
+                selector,
+                operator,
+                value,
+            } => {
+                validate_selector(selector)?;
+                let operator = match operator {
+                    MatchOperator::Equal => "==",
+                    MatchOperator::NotEqual => "!=",
+                    MatchOperator::IsEmpty => "is empty",
+                    MatchOperator::IsNotEmpty => "is not empty",
+                    MatchOperator::Contains => "contains",
+                    MatchOperator::NotContains => "not contains",
+                    MatchOperator::Matches => "matches",
+                    MatchOperator::NotMatches => "not matches",
+                };
+                out.push_str(selector);
+                out.push(' ');
+                out.push_str(operator);
+                if let Some(value) = value {
+                    out.push(' ');
+                    quote(value, out);
+                }
+            }
+            Filter::And(left, right) => {
+                left.render_operand(out, false)?;
+                out.push_str(" and ");
+                right.render_operand(out, false)?;
+            }
+            Filter::Or(left, right) => {
+                left.render(out)?;
+                out.push_str(" or ");
+                right.render(out)?;
+            }
+            Filter::Not(inner) => {
+                out.push_str("not ");
+                inner.render_operand(out, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the operand of an `and` or a `not`, wrapping it in parentheses
+    /// when it would otherwise bind differently.
+    fn render_operand(&self, out: &mut String, wrap_and: bool) -> Result<()> {
+        let wrap = match self {
+            Filter::Or(..) => true,
+            Filter::And(..) => wrap_and,
+            _ => false,
+        };
+        if wrap {
+            out.push('(');
+            self.render(out)?;
+            out.push(')');
+            Ok(())
+        } else {
+            self.render(out)
+        }
+    }
+}
+
+/// Selectors follow the bexpr grammar: identifiers matching
+/// `[a-zA-Z][a-zA-Z0-9_/]*`, each followed by `.` and another identifier or by
+/// an index such as `["my-key"]`.
+fn validate_selector(selector: &str) -> Result<()> {
+    let mut chars = selector.chars().peekable();
+    let mut valid = identifier(&mut chars);
+    while valid {
+        valid = match chars.next() {
+            None => return Ok(()),
+            Some('.') => identifier(&mut chars),
+            Some('[') => index(&mut chars),
+            Some(_) => false,
+        };
+    }
+    bail!("Invalid filter selector '{}'", selector);
+}
+
+fn identifier(chars: &mut Peekable<Chars>) -> bool {
+    if !matches!(chars.next(), Some(c) if c.is_ascii_alphabetic()) {
+        return false;
+    }
+    while chars
+        .next_if(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '/')
+        .is_some()
+    {}
+    true
+}
+
+/// Reads the quoted or raw string of an index and its closing bracket.
+fn index(chars: &mut Peekable<Chars>) -> bool {
+    match chars.next() {
+        Some('"') => loop {
+            match chars.next() {
+                Some('"') => break,
+                Some('\\') if chars.next().is_none() => return false,
+                Some(_) => {}
+                None => return false,
+            }
+        },
+        Some('`') => {
+            if !chars.any(|c| c == '`') {
+                return false;
+            }
+        }
+        _ => return false,
+    }
+    chars.next() == Some(']')
+}
+
+/// Quotes a value as a Go string literal, as Consul unquotes it.
+fn quote(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -17,6 +17,10 @@ pub struct HealthCheck {
     pub ServiceID: String,
     pub ServiceName: String,
     pub ServiceTags: Option<Vec<String>>,
+    pub Namespace: Option<String>,
+    pub Partition: Option<String>,
+    pub CreateIndex: u64,
+    pub ModifyIndex: u64,
 }
 
 #[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
@@ -0,0 +1,247 @@
+//! Hooks observing the requests sent to Consul, e.g. for logging, metrics or
+//! tracing.
+//!
+//! ```
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::Arc;
+//!
+//! use consul::interceptor::{Interceptor, RequestContext, RequestInfo, ResponseInfo};
+//! use consul::{Client, Config};
+//!
+//! #[derive(Debug, Default)]
+//! struct CallCounter(AtomicUsize);
+//!
+//! impl Interceptor for CallCounter {
+//!     fn on_response(&self, _: &RequestInfo, _: &ResponseInfo, _: RequestContext) {
+//!         self.0.fetch_add(1, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let counter = Arc::new(CallCounter::default());
+//! let client = Client::new(Config::new().unwrap()).with_interceptor(counter.clone());
+//! ```
+
+use std::any::Any;
+use std::fmt::{self, Debug};
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode};
+
+use crate::errors::{Error, Result};
+use crate::request::{parse_last_index, Response};
+use crate::transport::HttpRequest;
+
+/// Value of the redacted query parameters.
+pub const REDACTED: &str = "<redacted>";
+
+/// A request about to be sent, possibly several times when retried.
+#[derive(Clone, Debug)]
+pub struct RequestInfo {
+    pub method: Method,
+    /// Path of the endpoint, e.g. `/v1/kv/foo`.
+    pub path: String,
+    /// Query parameters, the ACL token being redacted.
+    pub params: Vec<(String, String)>,
+}
+
+/// Outcome of a request, once its retries are over.
+#[derive(Debug)]
+pub struct ResponseInfo<'a> {
+    /// Status of the response, if any was received.
+    pub status: Option<StatusCode>,
+    /// The `X-Consul-Index` header of the response.
+    pub index: Option<u64>,
+    /// Time spent on the request, including its retries.
+    pub latency: Duration,
+    pub attempts: u32,
+    /// Address of the agent which answered.
+    pub address: Option<&'a str>,
+    /// Error preventing to get a response.
+    pub error: Option<&'a Error>,
+}
+
+/// State of an interceptor kept from `on_request` to the `on_response` of
+/// the same request, such as a span or a start time.
+#[derive(Default)]
+pub struct RequestContext(Option<Box<dyn Any + Send>>);
+
+impl RequestContext {
+    pub fn new<T: Any + Send>(value: T) -> RequestContext {
+        RequestContext(Some(Box::new(value)))
+    }
+
+    /// The value of the context, if it is a `T`.
+    pub fn take<T: Any>(self) -> Option<T> {
+        self.0?.downcast().ok().map(|value| *value)
+    }
+}
+
+impl Debug for RequestContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RequestContext")
+            .field(&self.0.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+/// Hooks called around each request of a `Client`, in the order they were
+/// added with `Client::with_interceptor`.
+pub trait Interceptor: Debug + Send + Sync {
+    /// Called before the request is sent, returning the context passed to
+    /// `on_response` once it is over.
+    fn on_request(&self, _request: &RequestInfo) -> RequestContext {
+        RequestContext::default()
+    }
+
+    fn on_response(
+        &self,
+        _request: &RequestInfo,
+        _response: &ResponseInfo,
+        _context: RequestContext,
+    ) {
+    }
+}
+
+impl RequestInfo {
+    pub(crate) fn new(request: &HttpRequest) -> RequestInfo {
+        let params = request
+            .url
+            .query_pairs()
+            .map(|(name, value)| {
+                let value = if name == "token" {
+                    String::from(REDACTED)
+                } else {
+                    value.into_owned()
+                };
+                (name.into_owned(), value)
+            })
+            .collect();
+        RequestInfo {
+            method: request.method.clone(),
+            path: request.url.path().to_owned(),
+            params,
+        }
+    }
+}
+
+impl<'a> ResponseInfo<'a> {
+    pub(crate) fn new(
+        result: &'a Result<Response>,
+        attempts: u32,
+        latency: Duration,
+    ) -> ResponseInfo<'a> {
+        match result {
+            Ok(response) => ResponseInfo {
+                status: Some(response.status),
+                index: parse_last_index(&response.headers).unwrap_or(None),
+                latency,
+                attempts,
+                address: Some(&response.address),
+                error: None,
+            },
+            Err(e) => ResponseInfo {
+                status: None,
+                index: None,
+                latency,
+                attempts,
+                address: None,
+                error: Some(e),
+            },
+        }
+    }
+}
+
+/// Logs each request with the `log` crate, failed ones as warnings.
+#[cfg(feature = "log")]
+#[derive(Clone, Debug)]
+pub struct LogInterceptor {
+    pub level: log::Level,
+}
+
+#[cfg(feature = "log")]
+impl Default for LogInterceptor {
+    fn default() -> Self {
+        LogInterceptor {
+            level: log::Level::Debug,
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl Interceptor for LogInterceptor {
+    fn on_response(&self, request: &RequestInfo, response: &ResponseInfo, _: RequestContext) {
+        match (response.status, response.error) {
+            (Some(status), _) => {
+                let level = if status.is_client_error() || status.is_server_error() {
+                    log::Level::Warn
+                } else {
+                    self.level
+                };
+                log::log!(
+                    level,
+                    "{} {} {:?} -> {} index={:?} in {:?} ({} attempts)",
+                    request.method,
+                    request.path,
+                    request.params,
+                    status.as_u16(),
+                    response.index,
+                    response.latency,
+                    response.attempts
+                )
+            }
+            (None, error) => log::warn!(
+                "{} {} {:?} failed in {:?}: {}",
+                request.method,
+                request.path,
+                request.params,
+                response.latency,
+                error.map(|e| e.to_string()).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Records each request as a `consul_request` span of the `tracing` crate,
+/// opened when the request is sent and closed with its outcome, including its
+/// latency in milliseconds.
+#[cfg(feature = "tracing")]
+#[derive(Clone, Debug, Default)]
+pub struct TracingInterceptor;
+
+#[cfg(feature = "tracing")]
+impl Interceptor for TracingInterceptor {
+    fn on_request(&self, request: &RequestInfo) -> RequestContext {
+        use tracing::field::Empty;
+        RequestContext::new(tracing::info_span!(
+            "consul_request",
+            method = %request.method,
+            path = %request.path,
+            params = ?request.params,
+            status = Empty,
+            index = Empty,
+            latency_ms = Empty,
+            attempts = Empty,
+            address = Empty,
+        ))
+    }
+
+    fn on_response(&self, _: &RequestInfo, response: &ResponseInfo, context: RequestContext) {
+        let span = match context.take::<tracing::Span>() {
+            Some(span) => span,
+            None => return,
+        };
+        span.record("status", response.status.map(|s| s.as_u16()));
+        span.record("index", response.index);
+        span.record("latency_ms", response.latency.as_millis() as u64);
+        span.record("attempts", response.attempts);
+        span.record("address", response.address);
+        let _entered = span.enter();
+        match (response.status, response.error) {
+            (_, Some(e)) => tracing::warn!(error = %e, "request failed"),
+            (Some(status), _) if status.is_client_error() || status.is_server_error() => {
+                tracing::warn!("request failed")
+            }
+            _ => tracing::debug!("request completed"),
+        }
+    }
+}
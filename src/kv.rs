@@ -1,8 +1,12 @@
 use std::collections::HashMap;
+use std::fmt;
 
-use crate::errors::Error;
-use crate::errors::Result;
-use crate::request::{delete, get, get_vec, put};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::errors::{Error, ErrorKind, Result, ResultExt};
+use crate::request::{delete, get_raw, get_vec, put_raw};
+use crate::txn::{Transaction, Txn, TxnResult};
 use crate::{Client, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
 
 #[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
@@ -14,18 +18,312 @@ pub struct KVPair {
     pub ModifyIndex: Option<u64>,
     pub LockIndex: Option<u64>,
     pub Flags: Option<u64>,
-    pub Value: String,
+    /// The value, base64 encoded in the JSON of the Consul API.
+    #[serde(with = "base64_value")]
+    pub Value: Vec<u8>,
     pub Session: Option<String>,
+    pub Namespace: Option<String>,
+    pub Partition: Option<String>,
+}
+
+/// How `KV::delete_with` deletes keys.
+#[derive(Clone, Debug, Default)]
+pub struct DeleteOptions {
+    /// Deletes every key starting with the given one, used as a prefix.
+    pub recurse: bool,
+    /// Allows an empty prefix with `recurse`, deleting the whole store.
+    pub confirm_delete_all: bool,
+    /// Only deletes the key if its `ModifyIndex` is still this one, failing
+    /// with `ErrorKind::CasLost` otherwise, unless the key no longer exists.
+    /// Not supported with `recurse`.
+    pub cas: Option<u64>,
+}
+
+/// Format of the values read and written by `KV::get_typed` and
+/// `KV::put_typed`. Non exhaustive, as the `yaml` and `toml` features add
+/// variants.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Codec {
+    #[default]
+    Json,
+    #[cfg(feature = "yaml")]
+    Yaml,
+    #[cfg(feature = "toml")]
+    Toml,
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Codec::Json => "JSON",
+            #[cfg(feature = "yaml")]
+            Codec::Yaml => "YAML",
+            #[cfg(feature = "toml")]
+            Codec::Toml => "TOML",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Codec {
+    /// Encodes the value of a key, failing with `ErrorKind::ValueEncode`.
+    pub fn encode<T: Serialize + ?Sized>(self, key: &str, value: &T) -> Result<Vec<u8>> {
+        let error = || ErrorKind::ValueEncode(key.to_owned(), self.to_string());
+        match self {
+            Codec::Json => serde_json::to_vec(value).chain_err(error),
+            #[cfg(feature = "yaml")]
+            Codec::Yaml => serde_yaml::to_vec(value).chain_err(error),
+            #[cfg(feature = "toml")]
+            Codec::Toml => toml::to_vec(value).chain_err(error),
+        }
+    }
+
+    /// Decodes the value of a key, failing with `ErrorKind::ValueDecode`.
+    pub fn decode<T: DeserializeOwned>(self, key: &str, value: &[u8]) -> Result<T> {
+        let error = || ErrorKind::ValueDecode(key.to_owned(), self.to_string());
+        match self {
+            Codec::Json => serde_json::from_slice(value).chain_err(error),
+            #[cfg(feature = "yaml")]
+            Codec::Yaml => serde_yaml::from_slice(value).chain_err(error),
+            #[cfg(feature = "toml")]
+            Codec::Toml => toml::from_slice(value).chain_err(error),
+        }
+    }
+}
+
+/// A KV entry whose value was decoded by `KV::get_typed`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypedKVPair<T> {
+    pub value: T,
+    /// The entry, with its encoded value and the `ModifyIndex` to pass to a
+    /// check-and-set `KV::put_typed`.
+    pub pair: KVPair,
+}
+
+/// (De)serializes the KV values as the base64 text Consul uses, a missing
+/// value being empty.
+pub(crate) mod base64_value {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(value) => base64::decode(value).map_err(D::Error::custom),
+            None => Ok(Vec::new()),
+        }
+    }
 }
 
 #[allow(clippy::upper_case_acronyms)]
 pub trait KV {
     fn acquire(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
     fn delete(&self, _: &str, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    /// Deletes the key of the pair only if it was not modified since its
+    /// `ModifyIndex`, failing with `ErrorKind::CasLost` otherwise. Fails
+    /// without a request when the pair has no `ModifyIndex`.
+    fn delete_cas(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<WriteMeta>;
+    /// Deletes a key, or with `DeleteOptions::recurse` all the keys under a
+    /// prefix, returning whether any key was removed. The keys are read in
+    /// the transaction deleting them.
+    fn delete_with(
+        &self,
+        _: &str,
+        _: &DeleteOptions,
+        _: Option<&WriteOptions>,
+    ) -> Result<(bool, WriteMeta)>;
     fn get(&self, _: &str, _: Option<&QueryOptions>) -> Result<(Option<KVPair>, QueryMeta)>;
+    /// Reads a key and decodes its value with the codec. Not available on
+    /// `dyn KV`, being generic.
+    fn get_typed<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        codec: Codec,
+        o: Option<&QueryOptions>,
+    ) -> Result<(Option<TypedKVPair<T>>, QueryMeta)>
+    where
+        Self: Sized;
+    /// Reads the value of a key as is, without its metadata.
+    fn get_raw(&self, _: &str, _: Option<&QueryOptions>) -> Result<(Option<Vec<u8>>, QueryMeta)>;
+    /// Lists the keys starting with the prefix, without their values. With a
+    /// separator, the keys are only listed up to the first separator following
+    /// the prefix, e.g. `foo/` for `foo/bar` and `foo/baz` under `""` and `/`.
+    fn keys(
+        &self,
+        prefix: &str,
+        separator: Option<&str>,
+        _: Option<&QueryOptions>,
+    ) -> Result<(Vec<String>, QueryMeta)>;
     fn list(&self, _: &str, _: Option<&QueryOptions>) -> Result<(Vec<KVPair>, QueryMeta)>;
     fn put(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    /// Writes the pair only if its key was not modified since its
+    /// `ModifyIndex`, a missing index meaning the key must not exist yet.
+    /// Fails with `ErrorKind::CasLost` when another write happened meanwhile.
+    fn put_cas(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<WriteMeta>;
+    /// Writes a value encoded with the codec. With a `cas` index, the value
+    /// is only written as with `KV::put_cas`. Not available on `dyn KV`, being
+    /// generic.
+    fn put_typed<T: Serialize + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        codec: Codec,
+        cas: Option<u64>,
+        o: Option<&WriteOptions>,
+    ) -> Result<WriteMeta>
+    where
+        Self: Sized;
     fn release(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    /// Reads a key, computes its new value with `f` (given `None` when the key
+    /// is missing) and writes it with `put_cas`, starting over when the write
+    /// loses against a concurrent change, up to `max_attempts` times. Returns
+    /// the written value. Not available on `dyn KV`, being generic.
+    fn update<F>(
+        &self,
+        key: &str,
+        max_attempts: u32,
+        f: F,
+        o: Option<&WriteOptions>,
+    ) -> Result<(Vec<u8>, WriteMeta)>
+    where
+        F: FnMut(Option<&KVPair>) -> Result<Vec<u8>>,
+        Self: Sized;
+}
+
+/// Parameters of a check-and-set write of the pair.
+pub(crate) fn cas_params(pair: &KVPair) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    if let Some(i) = pair.Flags {
+        if i != 0 {
+            params.insert(String::from("flags"), i.to_string());
+        }
+    }
+    params.insert(
+        String::from("cas"),
+        pair.ModifyIndex.unwrap_or(0).to_string(),
+    );
+    params
+}
+
+/// Parameters of a check-and-set deletion, which needs the index the pair
+/// was read at.
+pub(crate) fn delete_cas_params(pair: &KVPair) -> Result<HashMap<String, String>> {
+    let index = match pair.ModifyIndex {
+        Some(index) => index,
+        None => bail!(
+            "The pair of key '{}' has no ModifyIndex to check the delete against",
+            pair.Key
+        ),
+    };
+    let mut params = HashMap::new();
+    params.insert(String::from("cas"), index.to_string());
+    Ok(params)
+}
+
+/// Transaction of a deletion, checking its options are consistent. Consul
+/// does not tell whether keys were deleted, so the keys under the deleted one
+/// are read first in the same transaction.
+pub(crate) fn delete_txn(key: &str, options: &DeleteOptions) -> Result<Txn> {
+    if options.recurse {
+        if options.cas.is_some() {
+            bail!("A check-and-set delete cannot be recursive");
+        }
+        if key.is_empty() && !options.confirm_delete_all {
+            bail!("Deleting the whole store requires DeleteOptions::confirm_delete_all");
+        }
+    } else if key.is_empty() {
+        bail!("The key to delete is empty");
+    }
+    let txn = Txn::new().kv_get_tree(key);
+    Ok(match (options.recurse, options.cas) {
+        (true, _) => txn.kv_delete_tree(key),
+        (false, Some(index)) => txn.kv_delete_cas(key, index),
+        (false, None) => txn.kv_delete(key),
+    })
+}
+
+/// Whether the transaction of `delete_txn` removed any key, turning the
+/// rollback of a check-and-set into `ErrorKind::CasLost`.
+pub(crate) fn delete_txn_result(
+    key: &str,
+    options: &DeleteOptions,
+    result: Result<(Vec<TxnResult>, WriteMeta)>,
+) -> Result<(bool, WriteMeta)> {
+    match result {
+        Ok((results, meta)) => {
+            let removed = results.iter().any(|result| match result {
+                TxnResult::KV(pair) => options.recurse || pair.Key == key,
+                _ => false,
+            });
+            Ok((removed, meta))
+        }
+        Err(Error(ErrorKind::TxnRolledBack(_), _)) if options.cas.is_some() => {
+            Err(ErrorKind::CasLost(key.to_owned()).into())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Decodes the value of a pair read by `KV::get_typed`.
+pub(crate) fn typed_pair<T: DeserializeOwned>(
+    pair: KVPair,
+    codec: Codec,
+) -> Result<TypedKVPair<T>> {
+    let value = codec.decode(&pair.Key, &pair.Value)?;
+    Ok(TypedKVPair { value, pair })
+}
+
+/// Parameters of a key listing.
+pub(crate) fn keys_params(separator: Option<&str>) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    params.insert(String::from("keys"), String::new());
+    if let Some(separator) = separator {
+        params.insert(String::from("separator"), separator.to_owned());
+    }
+    params
+}
+
+/// Turns the outcome of a check-and-set write into `ErrorKind::CasLost` when
+/// it was not applied.
+pub(crate) fn cas_result(key: &str, written: bool, meta: WriteMeta) -> Result<WriteMeta> {
+    if written {
+        Ok(meta)
+    } else {
+        Err(ErrorKind::CasLost(key.to_owned()).into())
+    }
+}
+
+/// Options of the reads preceding a write, targeting the same datacenter and
+/// tenancy.
+pub(crate) fn read_options(o: Option<&WriteOptions>) -> QueryOptions {
+    let o = o.cloned().unwrap_or_default();
+    QueryOptions {
+        datacenter: o.datacenter,
+        namespace: o.namespace,
+        partition: o.partition,
+        token: o.token,
+        ..Default::default()
+    }
+}
+
+/// The pair to write with `KV::put_cas` to replace the current one, if any.
+pub(crate) fn update_pair(key: &str, current: Option<KVPair>, value: Vec<u8>) -> KVPair {
+    match current {
+        Some(pair) => KVPair {
+            Value: value,
+            Session: None,
+            ..pair
+        },
+        None => KVPair {
+            Key: key.to_owned(),
+            Value: value,
+            ..Default::default()
+        },
+    }
 }
 
 impl KV for Client {
@@ -39,7 +337,7 @@ impl KV for Client {
         if let Some(ref session) = pair.Session {
             params.insert(String::from("acquire"), session.to_owned());
             let path = format!("/v1/kv/{}", pair.Key);
-            put(&path, Some(&pair.Value), &self.config, params, o)
+            put_raw(&path, &pair.Value, &self.config, params, o)
         } else {
             Err(Error::from("Session flag is required to acquire lock"))
         }
@@ -49,16 +347,66 @@ impl KV for Client {
         let path = format!("/v1/kv/{}", key);
         delete(&path, &self.config, HashMap::new(), options)
     }
+
+    fn delete_cas(&self, pair: &KVPair, options: Option<&WriteOptions>) -> Result<WriteMeta> {
+        let params = delete_cas_params(pair)?;
+        let path = format!("/v1/kv/{}", pair.Key);
+        let (deleted, meta) = delete(&path, &self.config, params, options)?;
+        cas_result(&pair.Key, deleted, meta)
+    }
+
+    fn delete_with(
+        &self,
+        key: &str,
+        options: &DeleteOptions,
+        o: Option<&WriteOptions>,
+    ) -> Result<(bool, WriteMeta)> {
+        let txn = delete_txn(key, options)?;
+        delete_txn_result(key, options, self.txn(&txn, o))
+    }
+
     fn get(
         &self,
         key: &str,
         options: Option<&QueryOptions>,
     ) -> Result<(Option<KVPair>, QueryMeta)> {
         let path = format!("/v1/kv/{}", key);
-        let x: Result<(Vec<KVPair>, QueryMeta)> = get(&path, &self.config, HashMap::new(), options);
+        let x: Result<(Vec<KVPair>, QueryMeta)> =
+            get_vec(&path, &self.config, HashMap::new(), options);
         x.map(|r| (r.0.first().cloned(), r.1))
     }
 
+    fn get_typed<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        codec: Codec,
+        o: Option<&QueryOptions>,
+    ) -> Result<(Option<TypedKVPair<T>>, QueryMeta)> {
+        let (pair, meta) = self.get(key, o)?;
+        Ok((pair.map(|p| typed_pair(p, codec)).transpose()?, meta))
+    }
+
+    fn get_raw(
+        &self,
+        key: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Option<Vec<u8>>, QueryMeta)> {
+        let mut params = HashMap::new();
+        params.insert(String::from("raw"), String::new());
+        let path = format!("/v1/kv/{}", key);
+        get_raw(&path, &self.config, params, options)
+    }
+
+    fn keys(
+        &self,
+        prefix: &str,
+        separator: Option<&str>,
+        o: Option<&QueryOptions>,
+    ) -> Result<(Vec<String>, QueryMeta)> {
+        let path = format!("/v1/kv/{}", prefix);
+        get_vec(&path, &self.config, keys_params(separator), o)
+    }
+
     fn list(&self, prefix: &str, o: Option<&QueryOptions>) -> Result<(Vec<KVPair>, QueryMeta)> {
         let mut params = HashMap::new();
         params.insert(String::from("recurse"), String::from(""));
@@ -74,7 +422,33 @@ impl KV for Client {
             }
         }
         let path = format!("/v1/kv/{}", pair.Key);
-        put(&path, Some(&pair.Value), &self.config, params, o)
+        put_raw(&path, &pair.Value, &self.config, params, o)
+    }
+
+    fn put_cas(&self, pair: &KVPair, o: Option<&WriteOptions>) -> Result<WriteMeta> {
+        let path = format!("/v1/kv/{}", pair.Key);
+        let (written, meta) = put_raw(&path, &pair.Value, &self.config, cas_params(pair), o)?;
+        cas_result(&pair.Key, written, meta)
+    }
+
+    fn put_typed<T: Serialize + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        codec: Codec,
+        cas: Option<u64>,
+        o: Option<&WriteOptions>,
+    ) -> Result<WriteMeta> {
+        let pair = KVPair {
+            Key: key.to_owned(),
+            Value: codec.encode(key, value)?,
+            ModifyIndex: cas,
+            ..Default::default()
+        };
+        match cas {
+            Some(_) => self.put_cas(&pair, o),
+            None => self.put(&pair, o).map(|(_, meta)| meta),
+        }
     }
 
     fn release(&self, pair: &KVPair, o: Option<&WriteOptions>) -> Result<(bool, WriteMeta)> {
@@ -87,9 +461,32 @@ impl KV for Client {
         if let Some(ref session) = pair.Session {
             params.insert(String::from("release"), session.to_owned());
             let path = format!("/v1/kv/{}", pair.Key);
-            put(&path, Some(&pair.Value), &self.config, params, o)
+            put_raw(&path, &pair.Value, &self.config, params, o)
         } else {
             Err(Error::from("Session flag is required to release a lock"))
         }
     }
+
+    fn update<F>(
+        &self,
+        key: &str,
+        max_attempts: u32,
+        mut f: F,
+        o: Option<&WriteOptions>,
+    ) -> Result<(Vec<u8>, WriteMeta)>
+    where
+        F: FnMut(Option<&KVPair>) -> Result<Vec<u8>>,
+    {
+        let query = read_options(o);
+        let mut attempt = 1;
+        loop {
+            let (current, _) = self.get(key, Some(&query))?;
+            let value = f(current.as_ref())?;
+            let pair = update_pair(key, current, value);
+            match self.put_cas(&pair, o) {
+                Err(Error(ErrorKind::CasLost(_), _)) if attempt < max_attempts => attempt += 1,
+                result => return result.map(|meta| (pair.Value, meta)),
+            }
+        }
+    }
 }
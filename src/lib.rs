@@ -7,23 +7,50 @@ extern crate error_chain;
 extern crate serde_derive;
 
 pub mod agent;
+#[cfg(feature = "async")]
+pub mod asynchronous;
 pub mod catalog;
 pub mod connect_ca;
+pub mod election;
 pub mod errors;
+pub mod filter;
 pub mod health;
+pub mod interceptor;
 pub mod kv;
+pub mod lock;
+pub mod semaphore;
 pub mod session;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+pub mod transport;
+pub mod txn;
+pub mod watch;
 
 mod request;
+mod tls;
+#[cfg(unix)]
+mod unix;
 
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
+use rand::Rng;
 use reqwest::blocking::Client as HttpClient;
 use reqwest::blocking::ClientBuilder;
+use url::Url;
 
 use errors::{Result, ResultExt};
+use filter::Filter;
+use interceptor::Interceptor;
+use tls::TlsSettings;
+#[cfg(feature = "async")]
+use transport::AsyncTransport;
+use transport::Transport;
 
 #[derive(Clone, Debug)]
 pub struct Client {
@@ -34,53 +61,111 @@ impl Client {
     pub fn new(config: Config) -> Self {
         Client { config }
     }
+
+    /// Adds an interceptor called around each request of this client.
+    pub fn with_interceptor(mut self, interceptor: Arc<dyn Interceptor>) -> Self {
+        self.config.interceptors.push(interceptor);
+        self
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Config {
+    /// Address of the agent, e.g. `http://127.0.0.1:8500`, or
+    /// `unix:///var/run/consul/consul.sock` for a Unix domain socket.
     pub address: String,
     pub datacenter: Option<String>,
+    /// Agents to fail over to, in order, when the one at `address` cannot be
+    /// reached. `tls.server_name` only applies to `address`.
+    pub fallback_addresses: Vec<String>,
+    pub failover: FailoverPolicy,
+    /// HTTP basic authentication, as `CONSUL_HTTP_AUTH`.
+    pub http_auth: Option<HttpBasicAuth>,
     pub http_client: HttpClient,
+    /// HTTP client used by the async `Client`, built with the same settings.
+    #[cfg(feature = "async")]
+    pub async_http_client: reqwest::Client,
+    /// Default namespace of the requests, as `CONSUL_NAMESPACE`.
+    pub namespace: Option<String>,
+    /// Default admin partition of the requests, as `CONSUL_PARTITION`.
+    pub partition: Option<String>,
+    pub retry: RetryPolicy,
+    /// TLS settings the HTTP clients were built with, see `Config::with_tls`.
+    pub tls: TlsConfig,
     pub token: Option<String>,
+    /// File holding the ACL token, as `CONSUL_HTTP_TOKEN_FILE`. Overrides
+    /// `token` unless the file is empty.
+    pub token_file: Option<TokenFile>,
+    /// Sends the requests of the blocking `Client` instead of `http_client`.
+    pub transport: Option<Arc<dyn Transport>>,
+    /// Sends the requests of the async `Client` instead of
+    /// `async_http_client`.
+    #[cfg(feature = "async")]
+    pub async_transport: Option<Arc<dyn AsyncTransport>>,
     pub wait_time: Option<Duration>,
+    /// Agent currently serving the requests, shared by the clones of the
+    /// `Config`.
+    active_agent: Arc<Mutex<ActiveAgent>>,
+    /// Interceptors of the `Client`, see `Client::with_interceptor`.
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    /// Address the HTTP clients map `tls.server_name` to.
+    server_name_address: Option<String>,
+}
+
+/// Builds the blocking HTTP client on a dedicated thread, as reqwest refuses to
+/// build one from within an async runtime, where the async `Client` may need
+/// to create its `Config`.
+fn build_http_client(builder: ClientBuilder) -> reqwest::Result<HttpClient> {
+    std::thread::spawn(move || builder.build())
+        .join()
+        .expect("reqwest client builder panicked")
 }
 
 impl Config {
     pub fn new() -> Result<Config> {
-        ClientBuilder::new()
-            .build()
-            .chain_err(|| "Failed to build reqwest client")
-            .map(|client| Config {
-                address: String::from("http://localhost:8500"),
-                datacenter: None,
-                http_client: client,
-                token: None,
-                wait_time: None,
-            })
+        Config::build(
+            String::from("http://localhost:8500"),
+            None,
+            TlsConfig::default(),
+        )
     }
 
+    /// Reads the configuration from the environment variables used by the
+    /// Consul CLI.
     pub fn new_from_env() -> Result<Config> {
-        let consul_addr = match env::var("CONSUL_HTTP_ADDR") {
-            Ok(val) => {
-                if val.starts_with("http") {
+        let scheme = if env_bool("CONSUL_HTTP_SSL")?.unwrap_or(false) {
+            "https"
+        } else {
+            "http"
+        };
+        let consul_addr = match env_var("CONSUL_HTTP_ADDR") {
+            Some(val) => {
+                if val.starts_with("http") || val.starts_with("unix://") {
                     val
                 } else {
-                    format!("http://{}", val)
+                    format!("{}://{}", scheme, val)
                 }
             }
-            Err(_e) => String::from("http://127.0.0.1:8500"),
+            None => format!("{}://127.0.0.1:8500", scheme),
         };
-        let consul_token = env::var("CONSUL_HTTP_TOKEN").ok();
-        ClientBuilder::new()
-            .build()
-            .chain_err(|| "Failed to build reqwest client")
-            .map(|client| Config {
-                address: consul_addr,
-                datacenter: None,
-                http_client: client,
-                token: consul_token,
-                wait_time: None,
-            })
+        Url::parse(&consul_addr)
+            .chain_err(|| format!("Invalid CONSUL_HTTP_ADDR '{}'", consul_addr))?;
+        let consul_token = env_var("CONSUL_HTTP_TOKEN");
+        let mut config = Config::build(consul_addr, consul_token, TlsConfig::from_env()?)?;
+        if let Some(path) = env_var("CONSUL_HTTP_TOKEN_FILE") {
+            let token_file = TokenFile::new(path);
+            token_file
+                .token()
+                .chain_err(|| "Invalid CONSUL_HTTP_TOKEN_FILE")?;
+            config.token_file = Some(token_file);
+        }
+        config.http_auth = env_var("CONSUL_HTTP_AUTH")
+            .map(|auth| auth.parse())
+            .transpose()?;
+        config.datacenter = env_var("CONSUL_DATACENTER");
+        config.namespace = env_var("CONSUL_NAMESPACE");
+        config.partition = env_var("CONSUL_PARTITION");
+        Ok(config)
     }
 
     pub fn new_from_consul_host(
@@ -88,38 +173,349 @@ impl Config {
         port: Option<u16>,
         token: Option<String>,
     ) -> Result<Config> {
-        ClientBuilder::new()
+        Config::build(
+            format!("{}:{}", host, port.unwrap_or(8500)),
+            token,
+            TlsConfig::default(),
+        )
+    }
+
+    /// Rebuilds the HTTP clients with the given TLS settings, keeping the rest
+    /// of the configuration.
+    pub fn with_tls(self, tls: TlsConfig) -> Result<Config> {
+        let built = Config::build(self.address.clone(), None, tls)?;
+        Ok(Config {
+            http_client: built.http_client,
+            #[cfg(feature = "async")]
+            async_http_client: built.async_http_client,
+            tls: built.tls,
+            server_name_address: built.server_name_address,
+            ..self
+        })
+    }
+
+    fn build(address: String, token: Option<String>, tls: TlsConfig) -> Result<Config> {
+        let settings = TlsSettings::load(&tls, &address)?;
+        let http_client = build_http_client(settings.apply(ClientBuilder::new()))
+            .chain_err(|| "Failed to build reqwest client")?;
+        #[cfg(feature = "async")]
+        let async_http_client = settings
+            .apply_async(reqwest::ClientBuilder::new())
             .build()
-            .chain_err(|| "Failed to build reqwest client")
-            .map(|client| Config {
-                address: format!("{}:{}", host, port.unwrap_or(8500)),
-                datacenter: None,
-                http_client: client,
-                token,
-                wait_time: None,
-            })
+            .chain_err(|| "Failed to build reqwest client")?;
+        Ok(Config {
+            address,
+            datacenter: None,
+            fallback_addresses: Vec::new(),
+            failover: FailoverPolicy::default(),
+            http_auth: None,
+            http_client,
+            #[cfg(feature = "async")]
+            async_http_client,
+            namespace: None,
+            partition: None,
+            retry: RetryPolicy::default(),
+            tls,
+            token,
+            token_file: None,
+            transport: None,
+            #[cfg(feature = "async")]
+            async_transport: None,
+            wait_time: None,
+            active_agent: Arc::new(Mutex::new(ActiveAgent::default())),
+            interceptors: Vec::new(),
+            server_name_address: settings.server_name_address(),
+        })
+    }
+}
+
+/// TLS settings of the connection to the agent, mirroring the Consul CLI
+/// environment variables.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// PEM bundle of CA certificates, as `CONSUL_CACERT`.
+    pub ca_file: Option<PathBuf>,
+    /// Directory of PEM CA certificates, as `CONSUL_CAPATH`.
+    pub ca_path: Option<PathBuf>,
+    /// PEM client certificate, as `CONSUL_CLIENT_CERT`.
+    pub client_cert: Option<PathBuf>,
+    /// Unencrypted PEM client key, in PKCS#8, PKCS#1 or SEC1 format, as
+    /// `CONSUL_CLIENT_KEY`.
+    pub client_key: Option<PathBuf>,
+    /// Name the agent certificate is verified against, as
+    /// `CONSUL_TLS_SERVER_NAME`. The HTTP clients map it to the IP addresses
+    /// `Config::address` resolves to when they are built, so the requests fail
+    /// once `address` is changed, until `Config::with_tls` is called again.
+    pub server_name: Option<String>,
+    /// Verifies the agent certificate, as `CONSUL_HTTP_SSL_VERIFY`.
+    pub verify: bool,
+}
+
+impl TlsConfig {
+    pub fn from_env() -> Result<TlsConfig> {
+        Ok(TlsConfig {
+            ca_file: env_var("CONSUL_CACERT").map(PathBuf::from),
+            ca_path: env_var("CONSUL_CAPATH").map(PathBuf::from),
+            client_cert: env_var("CONSUL_CLIENT_CERT").map(PathBuf::from),
+            client_key: env_var("CONSUL_CLIENT_KEY").map(PathBuf::from),
+            server_name: env_var("CONSUL_TLS_SERVER_NAME"),
+            verify: env_bool("CONSUL_HTTP_SSL_VERIFY")?.unwrap_or(true),
+        })
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            ca_file: None,
+            ca_path: None,
+            client_cert: None,
+            client_key: None,
+            server_name: None,
+            verify: true,
+        }
+    }
+}
+
+/// Reads an environment variable, ignoring it when empty like the Consul CLI.
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|val| !val.is_empty())
+}
+
+/// Reads a boolean environment variable the way the Consul CLI does.
+fn env_bool(name: &str) -> Result<Option<bool>> {
+    match env_var(name) {
+        Some(val) => match val.to_lowercase().as_str() {
+            "1" | "t" | "true" => Ok(Some(true)),
+            "0" | "f" | "false" => Ok(Some(false)),
+            _ => bail!("Invalid boolean value '{}' for {}", val, name),
+        },
+        None => Ok(None),
+    }
+}
+
+/// HTTP basic authentication credentials, parsed from `username[:password]`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HttpBasicAuth {
+    pub username: String,
+    pub password: Option<String>,
+}
+
+impl FromStr for HttpBasicAuth {
+    type Err = errors::Error;
+
+    fn from_str(s: &str) -> Result<HttpBasicAuth> {
+        let (username, password) = match s.split_once(':') {
+            Some((username, password)) => (username, Some(password.to_owned())),
+            None => (s, None),
+        };
+        if username.is_empty() {
+            bail!("Invalid CONSUL_HTTP_AUTH: the username is empty");
+        }
+        Ok(HttpBasicAuth {
+            username: username.to_owned(),
+            password,
+        })
+    }
+}
+
+/// File holding an ACL token, re-read whenever it is modified so that rotated
+/// tokens are picked up.
+#[derive(Clone, Debug)]
+pub struct TokenFile {
+    path: PathBuf,
+    cache: Arc<Mutex<Option<(SystemTime, String)>>>,
+}
+
+impl TokenFile {
+    pub fn new<P: Into<PathBuf>>(path: P) -> TokenFile {
+        TokenFile {
+            path: path.into(),
+            cache: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the token, reading the file again if it changed since last time.
+    pub fn token(&self) -> Result<String> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .chain_err(|| format!("Failed to read token file '{}'", self.path.display()))?;
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((cached_at, ref token)) = *cache {
+            if cached_at == modified {
+                return Ok(token.clone());
+            }
+        }
+        let token = fs::read_to_string(&self.path)
+            .chain_err(|| format!("Failed to read token file '{}'", self.path.display()))?
+            .trim()
+            .to_owned();
+        *cache = Some((modified, token.clone()));
+        Ok(token)
+    }
+}
+
+/// Retry policy applied to idempotent requests: reads are always retried,
+/// writes only when `WriteOptions::retry` is set.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    /// Backoff before the first retry, doubled on each following one.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// HTTP status codes worth retrying, besides connection failures and
+    /// timeouts.
+    pub retry_on_status: Vec<u16>,
+}
+
+impl RetryPolicy {
+    /// A policy performing a single attempt.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Exponential backoff to wait after the given failed attempt (starting at
+    /// 1), with a random jitter of up to half its value.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .initial_backoff
+            .checked_mul(1 << attempt.saturating_sub(1).min(31))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+        exp.mul_f64(1.0 - jitter)
+    }
+
+    pub(crate) fn should_retry_status(&self, status: u16) -> bool {
+        self.retry_on_status.contains(&status)
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, retrying on 429, 500 ("No cluster leader") and 503.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            retry_on_status: vec![429, 500, 503],
+        }
     }
 }
 
+/// Selection of the agent serving the requests among `Config::address` and
+/// `Config::fallback_addresses`.
+///
+/// Requests stick to the agent which last answered, failing over to the next
+/// agents in order when it cannot be connected to. Once failed over, the agent
+/// at `Config::address` is tried again first every `reprobe_interval`.
+#[derive(Clone, Debug)]
+pub struct FailoverPolicy {
+    pub reprobe_interval: Duration,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        FailoverPolicy {
+            reprobe_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Agent serving the requests: `0` for `Config::address`, else the index of
+/// the fallback address plus one.
+#[derive(Debug, Default)]
+struct ActiveAgent {
+    index: usize,
+    /// When the requests failed over, or last re-probed the primary agent.
+    failed_over_at: Option<Instant>,
+}
+
+/// ACL token sent with a single request, instead of the `Config` one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RequestToken {
+    Token(String),
+    /// Sends no token, the agent then using its default token, which is the
+    /// anonymous token only when the agent has none configured.
+    AgentDefault,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct QueryOptions {
     pub datacenter: Option<String>,
+    /// Namespace of the request, overriding `Config::namespace`.
+    pub namespace: Option<String>,
+    /// Admin partition of the request, overriding `Config::partition`.
+    pub partition: Option<String>,
+    /// Cluster peer to read the data imported from, on the endpoints
+    /// supporting it.
+    pub peer: Option<String>,
+    /// ACL token of the request, overriding `Config::token`.
+    pub token: Option<RequestToken>,
     pub wait_index: Option<u64>,
     pub wait_time: Option<Duration>,
+    /// Lets any server answer, possibly with stale data, instead of the leader.
+    pub allow_stale: bool,
+    /// Makes the leader confirm its leadership before answering.
+    pub require_consistent: bool,
+    /// Reads from the agent cache, on the endpoints supporting it.
+    pub use_cache: bool,
+    /// Server side filter of the results, on the endpoints supporting it.
+    pub filter: Option<Filter>,
+    /// Maximum age of a cached response, requires `use_cache`.
+    pub max_age: Option<Duration>,
+    /// How long a stale cached response may be served when the servers cannot
+    /// be reached, requires `use_cache`.
+    pub stale_if_error: Option<Duration>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct QueryMeta {
+    /// Address of the agent which served the request.
+    pub address: String,
     pub last_index: Option<u64>,
     pub request_time: Duration,
+    /// Number of attempts needed to get the response, see `RetryPolicy`.
+    pub attempts: u32,
+    /// Whether the answering server knew the cluster leader.
+    pub known_leader: bool,
+    /// Time since the answering server last heard from the leader.
+    pub last_contact: Duration,
+    /// Consistency mode actually used to answer, e.g. `stale`.
+    pub effective_consistency: Option<String>,
+    /// Whether the response came from the agent cache.
+    pub cache_hit: bool,
+    /// Age of the cached response, when served from the agent cache.
+    pub cache_age: Option<Duration>,
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct WriteOptions {
     pub datacenter: Option<String>,
+    /// Namespace of the request, overriding `Config::namespace`.
+    pub namespace: Option<String>,
+    /// Admin partition of the request, overriding `Config::partition`.
+    pub partition: Option<String>,
+    /// ACL token of the request, overriding `Config::token`.
+    pub token: Option<RequestToken>,
+    /// Retries the write according to the `Config` `RetryPolicy`. Only set it
+    /// for writes which are safe to replay.
+    pub retry: bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct WriteMeta {
+    /// Address of the agent which served the request.
+    pub address: String,
     pub request_time: Duration,
+    /// Number of attempts needed to get the response, see `RetryPolicy`.
+    pub attempts: u32,
 }
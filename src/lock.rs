@@ -0,0 +1,383 @@
+//! Distributed lock over a KV key, following the Consul leader election
+//! recipe and the `LockOpts` of the Go API.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use consul::lock::{Lock, LockOptions};
+//! use consul::{Client, Config};
+//!
+//! let client = Client::new(Config::new().unwrap());
+//! let lock = Lock::new(client, LockOptions::new("service/batch/leader"));
+//! if let Some(guard) = lock.lock(Some(Duration::from_secs(30))).unwrap() {
+//!     // Work while holding the lock, checking `guard.lost()` regularly
+//!     assert!(!guard.lost().is_lost());
+//! } // The lock is released when the guard is dropped
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::errors::Result;
+use crate::kv::{KVPair, KV};
+use crate::session::{renew_periodic, RenewHandle, Session, SessionEntry};
+use crate::watch::StopHandle;
+use crate::{Client, QueryOptions};
+
+/// Flags of the lock keys, telling them apart from the semaphore ones.
+pub const LOCK_FLAG_VALUE: u64 = 0x2ddc_cbc0_58a5_0c18;
+
+#[derive(Clone, Debug)]
+pub struct LockOptions {
+    pub key: String,
+    /// Value of the key while the lock is held.
+    pub value: Vec<u8>,
+    /// Session holding the lock. When unset, a session is created with
+    /// `session_name` and `session_ttl`, renewed while the lock is held and
+    /// destroyed when it is released.
+    pub session: Option<String>,
+    pub session_name: String,
+    pub session_ttl: Duration,
+    /// Maximum duration of each blocking query waiting for the key.
+    pub wait_time: Duration,
+    /// Delay before retrying to acquire a free key, which Consul refuses
+    /// during the lock-delay following the invalidation of its last holder.
+    pub retry_time: Duration,
+    /// Failed queries tolerated while monitoring the held lock, before
+    /// considering it lost.
+    pub monitor_retries: u32,
+    pub monitor_retry_time: Duration,
+}
+
+impl Default for LockOptions {
+    fn default() -> Self {
+        LockOptions {
+            key: String::new(),
+            value: Vec::new(),
+            session: None,
+            session_name: String::from("Consul API Lock"),
+            session_ttl: Duration::from_secs(15),
+            wait_time: Duration::from_secs(15),
+            retry_time: Duration::from_secs(5),
+            monitor_retries: 0,
+            monitor_retry_time: Duration::from_secs(2),
+        }
+    }
+}
+
+impl LockOptions {
+    pub fn new(key: &str) -> LockOptions {
+        LockOptions {
+            key: key.to_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Set once a held lock or semaphore slot is lost, because its session was
+/// invalidated or its keys deleted or taken over.
+#[derive(Clone, Debug, Default)]
+pub struct LostSignal {
+    lost: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl LostSignal {
+    pub fn is_lost(&self) -> bool {
+        *self.lost.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits until the lock is lost or the timeout elapsed, returning whether
+    /// it was lost.
+    pub fn wait(&self, timeout: Option<Duration>) -> bool {
+        let (lost, condvar) = &*self.lost;
+        let guard = lost.lock().unwrap_or_else(|e| e.into_inner());
+        let guard = match timeout {
+            Some(timeout) => {
+                condvar
+                    .wait_timeout_while(guard, timeout, |lost| !*lost)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0
+            }
+            None => condvar
+                .wait_while(guard, |lost| !*lost)
+                .unwrap_or_else(|e| e.into_inner()),
+        };
+        *guard
+    }
+
+    pub(crate) fn set(&self) {
+        let (lost, condvar) = &*self.lost;
+        *lost.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_all();
+    }
+}
+
+/// A session created for a lock or a semaphore, renewed in the background
+/// until destroyed.
+pub(crate) struct SessionKeeper {
+    renewer: RenewHandle,
+}
+
+impl SessionKeeper {
+    pub(crate) fn create(client: &Client, name: &str, ttl: Duration) -> Result<SessionKeeper> {
+        let ttl = Duration::from_secs(ttl.as_secs().max(1));
+        let entry = SessionEntry {
+            Name: Some(name.to_owned()),
+            TTL: Some(format!("{}s", ttl.as_secs())),
+            Behavior: Some(String::from("release")),
+            ..Default::default()
+        };
+        let (session, _) = client.create(&entry, None)?;
+        let id = match session.ID {
+            Some(id) => id,
+            None => bail!("Consul returned a session without ID"),
+        };
+        // A session gone for good shows on the keys it holds
+        let renewer = renew_periodic(client, &id, ttl, true, None)?;
+        Ok(SessionKeeper { renewer })
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        self.renewer.id()
+    }
+
+    pub(crate) fn destroy(self) -> Result<()> {
+        self.renewer.stop()
+    }
+}
+
+/// Rounds the wait time of a blocking query up to the seconds Consul expects,
+/// a zero wait meaning its default one.
+pub(crate) fn blocking_wait(wait: Duration) -> Duration {
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    Duration::from_secs(secs.max(1))
+}
+
+/// Time left before the deadline, `None` meaning no deadline.
+pub(crate) fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|d| d.saturating_duration_since(Instant::now()))
+}
+
+/// A lock over a KV key, held by at most one session at a time.
+pub struct Lock {
+    client: Client,
+    options: LockOptions,
+    held: Arc<AtomicBool>,
+}
+
+impl Lock {
+    pub fn new(client: Client, options: LockOptions) -> Lock {
+        Lock {
+            client,
+            options,
+            held: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Waits for the lock, up to the timeout if any. Returns `None` when the
+    /// timeout elapsed before the lock could be acquired.
+    pub fn lock(&self, timeout: Option<Duration>) -> Result<Option<LockGuard>> {
+        self.acquire(timeout.map(|t| Instant::now() + t))
+    }
+
+    /// Acquires the lock only if it is free, without waiting.
+    pub fn try_lock(&self) -> Result<Option<LockGuard>> {
+        self.acquire(Some(Instant::now()))
+    }
+
+    fn acquire(&self, deadline: Option<Instant>) -> Result<Option<LockGuard>> {
+        if self.held.swap(true, Ordering::SeqCst) {
+            bail!("The lock on '{}' is already held", self.options.key);
+        }
+        let (session, keeper) = match &self.options.session {
+            Some(session) => (session.clone(), None),
+            None => {
+                let created = SessionKeeper::create(
+                    &self.client,
+                    &self.options.session_name,
+                    self.options.session_ttl,
+                );
+                match created {
+                    Ok(keeper) => (keeper.id().to_owned(), Some(keeper)),
+                    Err(e) => {
+                        self.held.store(false, Ordering::SeqCst);
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        match self.acquire_with(&session, deadline) {
+            Ok(true) => {
+                let lost = LostSignal::default();
+                let stop = StopHandle::default();
+                self.monitor(&session, &lost, &stop);
+                Ok(Some(LockGuard {
+                    client: self.client.clone(),
+                    key: self.options.key.clone(),
+                    value: self.options.value.clone(),
+                    session,
+                    keeper,
+                    lost,
+                    stop,
+                    held: self.held.clone(),
+                    released: false,
+                }))
+            }
+            result => {
+                if let Some(keeper) = keeper {
+                    let _ = keeper.destroy();
+                }
+                self.held.store(false, Ordering::SeqCst);
+                result.map(|_| None)
+            }
+        }
+    }
+
+    /// Acquires the key for the session, returning `false` once the deadline
+    /// passed.
+    fn acquire_with(&self, session: &str, deadline: Option<Instant>) -> Result<bool> {
+        let options = &self.options;
+        let mut wait_index = None;
+        loop {
+            let wait = remaining(deadline).map_or(options.wait_time, |r| r.min(options.wait_time));
+            if wait_index.is_some() && wait == Duration::from_secs(0) {
+                return Ok(false);
+            }
+            let query = QueryOptions {
+                wait_index,
+                wait_time: Some(blocking_wait(wait)),
+                ..Default::default()
+            };
+            let (pair, meta) = self.client.get(&options.key, Some(&query))?;
+            if let Some(pair) = pair {
+                if pair.Flags != Some(LOCK_FLAG_VALUE) {
+                    bail!("The key '{}' is not used as a lock", options.key);
+                }
+                if pair.Session.as_deref() == Some(session) {
+                    return Ok(true);
+                }
+                if pair.Session.is_some() {
+                    // Held by another session, wait for its release
+                    wait_index = meta.last_index;
+                    continue;
+                }
+            }
+
+            let pair = KVPair {
+                Key: options.key.clone(),
+                Value: options.value.clone(),
+                Flags: Some(LOCK_FLAG_VALUE),
+                Session: Some(session.to_owned()),
+                ..Default::default()
+            };
+            if self.client.acquire(&pair, None)?.0 {
+                return Ok(true);
+            }
+            // Lost the race or within the lock-delay, retry later
+            match remaining(deadline) {
+                Some(left) if left == Duration::from_secs(0) => return Ok(false),
+                left => {
+                    thread::sleep(left.map_or(options.retry_time, |l| l.min(options.retry_time)))
+                }
+            }
+            wait_index = None;
+        }
+    }
+
+    /// Watches the held key in the background, signaling when the session no
+    /// longer holds it.
+    fn monitor(&self, session: &str, lost: &LostSignal, stop: &StopHandle) {
+        let client = self.client.clone();
+        let options = self.options.clone();
+        let (session, lost, stop) = (session.to_owned(), lost.clone(), stop.clone());
+        thread::spawn(move || {
+            let mut wait_index = None;
+            let mut retries = options.monitor_retries;
+            while !stop.is_stopped() {
+                let query = QueryOptions {
+                    wait_index,
+                    wait_time: Some(blocking_wait(options.wait_time)),
+                    ..Default::default()
+                };
+                match client.get(&options.key, Some(&query)) {
+                    Ok((pair, meta)) => {
+                        let holder = pair.and_then(|p| p.Session);
+                        if holder.as_deref() != Some(session.as_str()) {
+                            break;
+                        }
+                        wait_index = meta.last_index;
+                        retries = options.monitor_retries;
+                    }
+                    Err(_) if retries > 0 => {
+                        retries -= 1;
+                        stop.sleep(options.monitor_retry_time);
+                    }
+                    Err(_) => break,
+                }
+            }
+            if !stop.is_stopped() {
+                lost.set();
+            }
+        });
+    }
+}
+
+/// A held lock, released when dropped.
+pub struct LockGuard {
+    client: Client,
+    key: String,
+    value: Vec<u8>,
+    session: String,
+    keeper: Option<SessionKeeper>,
+    lost: LostSignal,
+    stop: StopHandle,
+    held: Arc<AtomicBool>,
+    released: bool,
+}
+
+impl LockGuard {
+    /// Signal set if the lock is lost while held.
+    pub fn lost(&self) -> &LostSignal {
+        &self.lost
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Releases the lock, and destroys its session if the lock created it.
+    pub fn unlock(mut self) -> Result<()> {
+        self.release()
+    }
+
+    fn release(&mut self) -> Result<()> {
+        if self.released {
+            return Ok(());
+        }
+        self.released = true;
+        self.stop.stop();
+        let pair = KVPair {
+            Key: self.key.clone(),
+            Value: self.value.clone(),
+            Flags: Some(LOCK_FLAG_VALUE),
+            Session: Some(self.session.clone()),
+            ..Default::default()
+        };
+        let released = self.client.release(&pair, None).map(|_| ());
+        let destroyed = match self.keeper.take() {
+            Some(keeper) => keeper.destroy(),
+            None => Ok(()),
+        };
+        self.held.store(false, Ordering::SeqCst);
+        released.and(destroyed)
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
@@ -1,149 +1,638 @@
 use std::collections::HashMap;
-use url::Url;
+use std::io;
+use url::{Position, Url};
 
 use std::str;
 use std::str::FromStr;
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use reqwest::blocking::Client as HttpClient;
-use reqwest::blocking::RequestBuilder;
-use reqwest::header::HeaderValue;
-use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE};
+use reqwest::{Method, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-use crate::errors::{Result, ResultExt};
-use crate::{Config, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
+use crate::errors::{Error, ErrorKind, Result, ResultExt};
+use crate::interceptor::{RequestInfo, ResponseInfo};
+use crate::transport::{HttpRequest, HttpResponse, Transport};
+#[cfg(unix)]
+use crate::unix;
+use crate::{Config, QueryMeta, QueryOptions, RequestToken, WriteMeta, WriteOptions};
 
-fn add_config_options(builder: RequestBuilder, config: &Config) -> RequestBuilder {
-    match &config.token {
-        Some(val) => builder.header("X-Consul-Token", val),
-        None => builder,
+/// Wait time of the blocking queries not setting one.
+const DEFAULT_WAIT_TIME: Duration = Duration::from_secs(300);
+
+/// A response from Consul, once its body has been read.
+pub(crate) struct Response {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+    /// Number of attempts needed to get this response.
+    pub attempts: u32,
+    /// Address of the agent which sent this response.
+    pub address: String,
+}
+
+impl Response {
+    pub fn new((response, address): (HttpResponse, String), attempts: u32) -> Response {
+        Response {
+            status: response.status,
+            headers: response.headers,
+            body: response.body,
+            attempts,
+            address,
+        }
+    }
+
+    pub fn into_query<R: DeserializeOwned>(self, start: Instant) -> Result<(R, QueryMeta)> {
+        if !self.status.is_success() {
+            return Err(status_error(self.status, &self.body));
+        }
+        let meta = self.query_meta(start)?;
+        Ok((decode_json(&self.body)?, meta))
+    }
+
+    /// Like `into_query`, but a 404 yields an empty list.
+    pub fn into_query_vec<R: DeserializeOwned>(
+        self,
+        start: Instant,
+    ) -> Result<(Vec<R>, QueryMeta)> {
+        if self.status == StatusCode::NOT_FOUND {
+            let meta = self.query_meta(start)?;
+            return Ok((Vec::new(), meta));
+        }
+        self.into_query(start)
+    }
+
+    /// Returns the body as is, a 404 yielding `None`.
+    pub fn into_raw(self, start: Instant) -> Result<(Option<Vec<u8>>, QueryMeta)> {
+        let meta = self.query_meta(start)?;
+        if self.status == StatusCode::NOT_FOUND {
+            return Ok((None, meta));
+        }
+        if !self.status.is_success() {
+            return Err(status_error(self.status, &self.body));
+        }
+        Ok((Some(self.body), meta))
+    }
+
+    pub fn into_write<R: DeserializeOwned>(self, start: Instant) -> Result<(R, WriteMeta)> {
+        if !self.status.is_success() {
+            return Err(status_error(self.status, &self.body));
+        }
+        Ok((
+            decode_json(&self.body)?,
+            WriteMeta {
+                address: self.address,
+                request_time: Instant::now() - start,
+                attempts: self.attempts,
+            },
+        ))
+    }
+
+    fn query_meta(&self, start: Instant) -> Result<QueryMeta> {
+        let header = |name: &str| self.headers.get(name).and_then(|v| v.to_str().ok());
+        Ok(QueryMeta {
+            address: self.address.clone(),
+            last_index: parse_last_index(&self.headers)?,
+            request_time: Instant::now() - start,
+            attempts: self.attempts,
+            known_leader: header("X-Consul-KnownLeader") == Some("true"),
+            last_contact: header("X-Consul-LastContact")
+                .and_then(|ms| ms.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or_default(),
+            effective_consistency: header("X-Consul-Effective-Consistency").map(String::from),
+            cache_hit: header("X-Cache") == Some("HIT"),
+            cache_age: header("Age")
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs),
+        })
     }
 }
 
-pub fn get_vec<R: DeserializeOwned>(
-    path: &str,
+/// Body of a write request.
+pub(crate) struct Body {
+    bytes: Vec<u8>,
+    content_type: &'static str,
+}
+
+impl Body {
+    pub fn json<T: Serialize>(value: &T) -> Result<Body> {
+        Ok(Body {
+            bytes: serde_json::to_vec(value).chain_err(|| "Failed to encode request body")?,
+            content_type: "application/json",
+        })
+    }
+
+    /// Bytes sent as is, such as KV values.
+    pub fn raw(bytes: &[u8]) -> Body {
+        Body {
+            bytes: bytes.to_vec(),
+            content_type: "application/octet-stream",
+        }
+    }
+}
+
+/// Adds the namespace and admin partition of a request, falling back to the
+/// defaults of the `Config`.
+fn add_tenancy_params(
+    params: &mut HashMap<String, String>,
+    config: &Config,
+    namespace: Option<&String>,
+    partition: Option<&String>,
+) {
+    if let Some(ns) = namespace.or(config.namespace.as_ref()) {
+        params.insert(String::from("ns"), ns.to_owned());
+    }
+    if let Some(partition) = partition.or(config.partition.as_ref()) {
+        params.insert(String::from("partition"), partition.to_owned());
+    }
+}
+
+/// Adds the query parameters derived from the `Config` and the `QueryOptions`
+/// of a read request.
+pub(crate) fn add_query_params(
+    params: &mut HashMap<String, String>,
     config: &Config,
-    mut params: HashMap<String, String>,
     options: Option<&QueryOptions>,
-) -> Result<(Vec<R>, QueryMeta)> {
+) -> Result<()> {
     let datacenter: Option<&String> = options
         .and_then(|o| o.datacenter.as_ref())
-        .or_else(|| config.datacenter.as_ref());
+        .or(config.datacenter.as_ref());
 
     if let Some(dc) = datacenter {
         params.insert(String::from("dc"), dc.to_owned());
     }
+    add_tenancy_params(
+        params,
+        config,
+        options.and_then(|o| o.namespace.as_ref()),
+        options.and_then(|o| o.partition.as_ref()),
+    );
     if let Some(options) = options {
+        if let Some(ref peer) = options.peer {
+            params.insert(String::from("peer"), peer.to_owned());
+        }
         if let Some(index) = options.wait_index {
             params.insert(String::from("index"), index.to_string());
         }
         if let Some(wait_time) = options.wait_time {
             params.insert(String::from("wait"), format!("{}s", wait_time.as_secs()));
         }
+        if let Some(ref filter) = options.filter {
+            params.insert(String::from("filter"), filter.to_expression()?);
+        }
+        if options.allow_stale && options.require_consistent {
+            bail!("allow_stale and require_consistent cannot be used together");
+        }
+        if options.allow_stale {
+            params.insert(String::from("stale"), String::new());
+        }
+        if options.require_consistent {
+            params.insert(String::from("consistent"), String::new());
+        }
+        if options.use_cache {
+            params.insert(String::from("cached"), String::new());
+        } else if options.max_age.is_some() || options.stale_if_error.is_some() {
+            bail!("max_age and stale_if_error require use_cache");
+        }
     }
+    Ok(())
+}
 
-    let url_str = format!("{}{}", config.address, path);
-    let url =
-        Url::parse_with_params(&url_str, params.iter()).chain_err(|| "Failed to parse URL")?;
-    let start = Instant::now();
-    let request_builder = add_config_options(config.http_client.get(url), &config);
-    let response = request_builder.send();
-    response
-        .chain_err(|| "HTTP request to consul failed")
-        .and_then(|r| {
-            let x: Option<Result<u64>> = r
-                .headers()
-                .get("X-Consul-Index")
-                .map(|value: &HeaderValue| value.as_bytes())
-                .map(|bytes| {
-                    str::from_utf8(bytes)
-                        .chain_err(|| "Failed to parse valid UT8 for last index")
-                        .and_then(|s| {
-                            u64::from_str(s)
-                                .chain_err(|| "Failed to parse valid number for last index")
-                        })
-                });
-            let j = if r.status() != StatusCode::NOT_FOUND {
-                r.json().chain_err(|| "Failed to parse JSON response")?
-            } else {
-                Vec::new()
-            };
-            match x {
-                Some(r) => Ok((j, Some(r?))),
-                None => Ok((j, None)),
-            }
-        })
-        .map(|x: (Vec<R>, Option<u64>)| {
-            (
-                x.0,
-                QueryMeta {
-                    last_index: x.1,
-                    request_time: Instant::now() - start,
-                },
-            )
-        })
+/// Timeout of a blocking query, which the agent may hold for its wait time
+/// plus up to a sixteenth of jitter, longer than the default client timeout.
+pub(crate) fn blocking_query_timeout(options: Option<&QueryOptions>) -> Option<Duration> {
+    let options = options?;
+    options.wait_index?;
+    let wait = options.wait_time.unwrap_or(DEFAULT_WAIT_TIME);
+    Some(wait + wait / 16 + Duration::from_secs(10))
 }
 
-pub fn get<R: DeserializeOwned>(
-    path: &str,
+/// Headers derived from the `QueryOptions` of a read request.
+pub(crate) fn query_headers(options: Option<&QueryOptions>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(options) = options {
+        let mut cache_control = Vec::new();
+        if let Some(max_age) = options.max_age {
+            cache_control.push(format!("max-age={}", max_age.as_secs()));
+        }
+        if let Some(stale_if_error) = options.stale_if_error {
+            cache_control.push(format!("stale-if-error={}", stale_if_error.as_secs()));
+        }
+        if !cache_control.is_empty() {
+            let value = HeaderValue::from_str(&cache_control.join(", "))
+                .expect("Cache-Control is a valid header value");
+            headers.insert(CACHE_CONTROL, value);
+        }
+    }
+    headers
+}
+
+/// Adds the query parameters derived from the `Config` and the `WriteOptions`
+/// of a write request.
+pub(crate) fn add_write_params(
+    params: &mut HashMap<String, String>,
     config: &Config,
-    mut params: HashMap<String, String>,
-    options: Option<&QueryOptions>,
-) -> Result<(R, QueryMeta)> {
+    options: Option<&WriteOptions>,
+) {
     let datacenter: Option<&String> = options
         .and_then(|o| o.datacenter.as_ref())
-        .or_else(|| config.datacenter.as_ref());
+        .or(config.datacenter.as_ref());
 
     if let Some(dc) = datacenter {
         params.insert(String::from("dc"), dc.to_owned());
     }
-    if let Some(options) = options {
-        if let Some(index) = options.wait_index {
-            params.insert(String::from("index"), index.to_string());
+    add_tenancy_params(
+        params,
+        config,
+        options.and_then(|o| o.namespace.as_ref()),
+        options.and_then(|o| o.partition.as_ref()),
+    );
+}
+
+pub(crate) fn build_url(
+    config: &Config,
+    path: &str,
+    params: &HashMap<String, String>,
+) -> Result<Url> {
+    let url_str = format!("{}{}", base_url(&config.address)?, path);
+    let mut url = Url::parse_with_params(&url_str, params.iter())
+        .chain_err(|| ErrorKind::BadUrl(url_str.clone()))?;
+    // The HTTP clients resolve the TLS server name to the agent address
+    if let Some(ref server_name) = config.tls.server_name {
+        if url.scheme() == "https" {
+            if config.server_name_address.as_deref() != Some(config.address.as_str()) {
+                bail!(
+                    "The TLS server name is mapped to another address than '{}', \
+                     call Config::with_tls after changing the address",
+                    config.address
+                );
+            }
+            url.set_host(Some(server_name))
+                .chain_err(|| ErrorKind::BadUrl(server_name.to_owned()))?;
         }
-        if let Some(wait_time) = options.wait_time {
-            params.insert(String::from("wait"), format!("{}s", wait_time.as_secs()));
+    }
+    Ok(url)
+}
+
+/// Base URL of the requests: requests to a Unix domain socket still need an
+/// HTTP URL, its host being ignored.
+fn base_url(address: &str) -> Result<&str> {
+    if address.starts_with("unix://") {
+        if cfg!(unix) {
+            Ok("http://localhost")
+        } else {
+            bail!("Unix domain sockets are not supported on this platform")
         }
+    } else {
+        Ok(address)
+    }
+}
+
+/// Reads the `X-Consul-Index` header of a response, if any.
+pub(crate) fn parse_last_index(headers: &HeaderMap) -> Result<Option<u64>> {
+    headers
+        .get("X-Consul-Index")
+        .map(|bytes: &HeaderValue| -> Result<u64> {
+            bytes
+                .to_str()
+                .chain_err(|| "Failed to parse valid UT8 for last index")
+                .and_then(|s: &str| -> Result<u64> {
+                    u64::from_str(s).chain_err(|| "Failed to parse valid number for last index")
+                })
+        })
+        .transpose()
+}
+
+/// Maps a non-2xx response to the matching `ErrorKind`, keeping the body sent
+/// by Consul as it usually explains the failure.
+pub(crate) fn status_error(status: StatusCode, body: &[u8]) -> Error {
+    let body = String::from_utf8_lossy(body).trim().to_owned();
+    match status {
+        StatusCode::FORBIDDEN => ErrorKind::PermissionDenied(body).into(),
+        StatusCode::NOT_FOUND => ErrorKind::NotFound(body).into(),
+        _ => ErrorKind::ConsulError(status.as_u16(), body).into(),
+    }
+}
+
+pub(crate) fn decode_json<R: DeserializeOwned>(body: &[u8]) -> Result<R> {
+    serde_json::from_slice(body).chain_err(|| ErrorKind::Decode)
+}
+
+/// Default ACL token of the requests: the content of the `Config` token file
+/// unless empty, else the `Config` token, as the Consul CLI.
+fn request_token(config: &Config) -> Result<Option<String>> {
+    if let Some(ref file) = config.token_file {
+        let token = file.token()?;
+        if !token.is_empty() {
+            return Ok(Some(token));
+        }
+    }
+    Ok(config.token.clone().filter(|t| !t.is_empty()))
+}
+
+/// Adds the ACL token and basic authentication headers of a request, its
+/// token defaulting to the `Config` one.
+fn add_config_headers(
+    headers: &mut HeaderMap,
+    config: &Config,
+    token: Option<&RequestToken>,
+) -> Result<()> {
+    let token = match token {
+        Some(RequestToken::Token(token)) => Some(token.clone()).filter(|t| !t.is_empty()),
+        Some(RequestToken::AgentDefault) => None,
+        None => request_token(config)?,
+    };
+    if let Some(token) = token {
+        let value = HeaderValue::from_str(&token).chain_err(|| "Invalid ACL token")?;
+        headers.insert("X-Consul-Token", value);
     }
+    if let Some(ref auth) = config.http_auth {
+        let credentials = format!(
+            "{}:{}",
+            auth.username,
+            auth.password.as_deref().unwrap_or_default()
+        );
+        let value = HeaderValue::from_str(&format!("Basic {}", base64::encode(credentials)))
+            .chain_err(|| "Invalid HTTP basic authentication")?;
+        headers.insert(AUTHORIZATION, value);
+    }
+    Ok(())
+}
 
-    let url_str = format!("{}{}", config.address, path);
-    let url =
-        Url::parse_with_params(&url_str, params.iter()).chain_err(|| "Failed to parse URL")?;
+/// Builds a read request.
+pub(crate) fn query_request(
+    config: &Config,
+    path: &str,
+    mut params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<HttpRequest> {
+    add_query_params(&mut params, config, options)?;
+    let mut headers = query_headers(options);
+    add_config_headers(&mut headers, config, options.and_then(|o| o.token.as_ref()))?;
+    Ok(HttpRequest {
+        method: Method::GET,
+        url: build_url(config, path, &params)?,
+        headers,
+        body: None,
+        timeout: blocking_query_timeout(options),
+    })
+}
+
+/// Builds a write request, sending its body as JSON.
+pub(crate) fn write_request(
+    config: &Config,
+    method: Method,
+    path: &str,
+    body: Option<Body>,
+    mut params: HashMap<String, String>,
+    options: Option<&WriteOptions>,
+) -> Result<HttpRequest> {
+    add_write_params(&mut params, config, options);
+    let mut headers = HeaderMap::new();
+    add_config_headers(&mut headers, config, options.and_then(|o| o.token.as_ref()))?;
+    if let Some(ref body) = body {
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(body.content_type));
+    }
+    Ok(HttpRequest {
+        method,
+        url: build_url(config, path, &params)?,
+        headers,
+        body: body.map(|b| b.bytes),
+        timeout: None,
+    })
+}
+
+/// Indexes of the agents to send a request to, in order: the active agent,
+/// preceded by the primary one when due for a re-probe, then the others.
+pub(crate) fn agent_order(config: &Config) -> Vec<usize> {
+    let count = 1 + config.fallback_addresses.len();
+    let active = config
+        .active_agent
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let mut order = Vec::with_capacity(count);
+    let index = Some(active.index).filter(|i| *i < count).unwrap_or(0);
+    let reprobe = active
+        .failed_over_at
+        .map(|at| at.elapsed() >= config.failover.reprobe_interval)
+        .unwrap_or(true);
+    if index != 0 && reprobe {
+        order.push(0);
+    }
+    order.push(index);
+    order.extend((0..count).filter(|i| *i != index && !(*i == 0 && reprobe)));
+    order
+}
+
+/// Address and URL of a request sent to the given agent.
+pub(crate) fn agent_url<'a>(config: &'a Config, index: usize, url: &Url) -> Result<(&'a str, Url)> {
+    if index == 0 {
+        return Ok((&config.address, url.clone()));
+    }
+    let address = &config.fallback_addresses[index - 1];
+    let url_str = format!("{}{}", base_url(address)?, &url[Position::BeforePath..]);
+    let url = Url::parse(&url_str).chain_err(|| ErrorKind::BadUrl(url_str.clone()))?;
+    Ok((address, url))
+}
+
+/// Makes the requests stick to the agent which answered, `primary_failed`
+/// telling whether the primary agent was tried first.
+pub(crate) fn select_agent(config: &Config, index: usize, primary_failed: bool) {
+    let mut active = config
+        .active_agent
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if index == 0 {
+        active.failed_over_at = None;
+    } else if index != active.index || primary_failed {
+        active.failed_over_at = Some(Instant::now());
+    }
+    active.index = index;
+}
+
+/// Whether the agent could not be connected to, in which case the request was
+/// not sent and another agent can be tried.
+///
+/// Custom transports report it with a `std::io::Error` of kind
+/// `ConnectionRefused` in the error chain.
+pub(crate) fn is_connect_error(e: &Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(err) = source {
+        if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+            if e.is_connect() {
+                return true;
+            }
+        }
+        if let Some(e) = err.downcast_ref::<io::Error>() {
+            if matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::NotFound
+                    | io::ErrorKind::AddrNotAvailable
+            ) {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Whether a failed request is worth retrying: the agent could not be
+/// reached, dropped the connection or did not answer in time. Other errors,
+/// such as invalid URLs or certificates, fail the same way on each attempt.
+pub(crate) fn is_transient_error(e: &Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(err) = source {
+        if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() {
+                return true;
+            }
+        }
+        #[cfg(feature = "async")]
+        {
+            if err.is::<tokio::time::error::Elapsed>() {
+                return true;
+            }
+        }
+        if let Some(e) = err.downcast_ref::<io::Error>() {
+            if matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::NotFound
+                    | io::ErrorKind::AddrNotAvailable
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::WouldBlock
+            ) {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Sends a request to the given agent through the `Transport` of the
+/// `Config`, or by default over HTTP, or over the agent Unix domain socket
+/// when its address uses the `unix://` scheme.
+fn send_to(config: &Config, address: &str, request: HttpRequest) -> Result<HttpResponse> {
+    if let Some(ref transport) = config.transport {
+        return transport.send(request);
+    }
+    #[cfg(unix)]
+    {
+        if let Some(path) = unix::socket_path(address) {
+            return unix::send(path, &request).chain_err(|| ErrorKind::Transport);
+        }
+    }
+    Transport::send(&config.http_client, request)
+}
+
+/// Sends a request to the active agent, failing over to the next ones when it
+/// cannot be connected to. Returns the response and the agent address.
+fn send(config: &Config, request: &HttpRequest) -> Result<(HttpResponse, String)> {
+    let mut primary_failed = false;
+    let mut error = None;
+    for index in agent_order(config) {
+        let (address, url) = agent_url(config, index, &request.url)?;
+        let mut request = request.clone();
+        request.url = url;
+        match send_to(config, address, request) {
+            Ok(response) => {
+                select_agent(config, index, primary_failed);
+                return Ok((response, address.to_owned()));
+            }
+            Err(e) if is_connect_error(&e) => {
+                primary_failed |= index == 0;
+                error = Some(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(error.expect("at least one agent is tried"))
+}
+
+/// Sends the request, retrying it according to the `RetryPolicy` of the
+/// `Config` when `idempotent` is set, and calls the interceptors of the
+/// `Client` around it.
+fn execute(config: &Config, idempotent: bool, request: HttpRequest) -> Result<Response> {
+    let info = if config.interceptors.is_empty() {
+        None
+    } else {
+        Some(RequestInfo::new(&request))
+    };
+    let mut contexts: Vec<_> = match info {
+        Some(ref info) => config
+            .interceptors
+            .iter()
+            .map(|interceptor| interceptor.on_request(info))
+            .collect(),
+        None => Vec::new(),
+    };
     let start = Instant::now();
-    let request_builder = add_config_options(config.http_client.get(url), &config);
-    let response = request_builder.send();
-    response
-        .chain_err(|| "HTTP request to consul failed")
-        .and_then(|r| {
-            let x: Option<Result<u64>> =
-                r.headers()
-                    .get("X-Consul-Index")
-                    .map(|bytes: &HeaderValue| -> Result<u64> {
-                        bytes
-                            .to_str()
-                            .chain_err(|| "Failed to parse valid UT8 for last index")
-                            .and_then(|s: &str| -> Result<u64> {
-                                u64::from_str(s)
-                                    .chain_err(|| "Failed to parse valid number for last index")
-                            })
-                    });
-            let j = r.json().chain_err(|| "Failed to parse JSON response")?;
-            match x {
-                Some(r) => Ok((j, Some(r?))),
-                None => Ok((j, None)),
+    let policy = &config.retry;
+    let mut attempts = 1;
+    loop {
+        let result = send(config, &request).map(|r| Response::new(r, attempts));
+        let retry = match &result {
+            Ok(r) => policy.should_retry_status(r.status.as_u16()),
+            Err(e) => is_transient_error(e),
+        };
+        if !idempotent || !retry || attempts >= policy.max_attempts {
+            if let Some(ref info) = info {
+                let response = ResponseInfo::new(&result, attempts, start.elapsed());
+                let contexts = contexts.drain(..);
+                for (interceptor, context) in config.interceptors.iter().zip(contexts) {
+                    interceptor.on_response(info, &response, context);
+                }
             }
-        })
-        .map(|x: (R, Option<u64>)| {
-            (
-                x.0,
-                QueryMeta {
-                    last_index: x.1,
-                    request_time: Instant::now() - start,
-                },
-            )
-        })
+            return result;
+        }
+        thread::sleep(policy.backoff(attempts));
+        attempts += 1;
+    }
+}
+
+pub fn get_vec<R: DeserializeOwned>(
+    path: &str,
+    config: &Config,
+    params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<(Vec<R>, QueryMeta)> {
+    let request = query_request(config, path, params, options)?;
+    let start = Instant::now();
+    execute(config, true, request)?.into_query_vec(start)
+}
+
+pub fn get<R: DeserializeOwned>(
+    path: &str,
+    config: &Config,
+    params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<(R, QueryMeta)> {
+    let request = query_request(config, path, params, options)?;
+    let start = Instant::now();
+    execute(config, true, request)?.into_query(start)
+}
+
+/// Reads the raw body of a response, e.g. of the `?raw` KV reads.
+pub fn get_raw(
+    path: &str,
+    config: &Config,
+    params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<(Option<Vec<u8>>, QueryMeta)> {
+    let request = query_request(config, path, params, options)?;
+    let start = Instant::now();
+    execute(config, true, request)?.into_raw(start)
 }
 
 pub fn delete<R: DeserializeOwned>(
@@ -152,18 +641,18 @@ pub fn delete<R: DeserializeOwned>(
     params: HashMap<String, String>,
     options: Option<&WriteOptions>,
 ) -> Result<(R, WriteMeta)> {
-    let req = |http_client: &HttpClient, url: Url| -> RequestBuilder { http_client.delete(url) };
-    write_with_body(path, None as Option<&()>, config, params, options, req)
+    write_with_body(Method::DELETE, path, None, config, params, options)
 }
 
 /*
 pub fn post<T: Serialize, R: DeserializeOwned>(path: &str,
                                                body: Option<&T>,
                                                config: &Config,
+                                               params: HashMap<String, String>,
                                                options: Option<&WriteOptions>)
                                                -> Result<(R, WriteMeta)> {
-    let req = |http_client: &HttpClient, url: Url| -> RequestBuilder { http_client.post(url) };
-    write_with_body(path, body, config, options, req)
+    let body = body.map(Body::json).transpose()?;
+    write_with_body(Method::POST, path, body, config, params, options)
 }
 */
 pub fn put<T: Serialize, R: DeserializeOwned>(
@@ -173,50 +662,32 @@ pub fn put<T: Serialize, R: DeserializeOwned>(
     params: HashMap<String, String>,
     options: Option<&WriteOptions>,
 ) -> Result<(R, WriteMeta)> {
-    let req = |http_client: &HttpClient, url: Url| -> RequestBuilder { http_client.put(url) };
-    write_with_body(path, body, config, params, options, req)
+    let body = body.map(Body::json).transpose()?;
+    write_with_body(Method::PUT, path, body, config, params, options)
 }
 
-fn write_with_body<T: Serialize, R: DeserializeOwned, F>(
+/// Writes the body as is, e.g. a KV value.
+pub fn put_raw<R: DeserializeOwned>(
     path: &str,
-    body: Option<&T>,
+    body: &[u8],
     config: &Config,
-    mut params: HashMap<String, String>,
+    params: HashMap<String, String>,
     options: Option<&WriteOptions>,
-    req: F,
-) -> Result<(R, WriteMeta)>
-where
-    F: Fn(&HttpClient, Url) -> RequestBuilder,
-{
-    let start = Instant::now();
-    let datacenter: Option<&String> = options
-        .and_then(|o| o.datacenter.as_ref())
-        .or_else(|| config.datacenter.as_ref());
-
-    if let Some(dc) = datacenter {
-        params.insert(String::from("dc"), dc.to_owned());
-    }
+) -> Result<(R, WriteMeta)> {
+    let body = Some(Body::raw(body));
+    write_with_body(Method::PUT, path, body, config, params, options)
+}
 
-    let url_str = format!("{}{}", config.address, path);
-    let url =
-        Url::parse_with_params(&url_str, params.iter()).chain_err(|| "Failed to parse URL")?;
-    let builder = req(&config.http_client, url);
-    let builder = if let Some(b) = body {
-        builder.json(b)
-    } else {
-        builder
-    };
-    let builder = add_config_options(builder, &config);
-    builder
-        .send()
-        .chain_err(|| "HTTP request to consul failed")
-        .and_then(|x| x.json().chain_err(|| "Failed to parse JSON"))
-        .map(|x| {
-            (
-                x,
-                WriteMeta {
-                    request_time: Instant::now() - start,
-                },
-            )
-        })
+fn write_with_body<R: DeserializeOwned>(
+    method: Method,
+    path: &str,
+    body: Option<Body>,
+    config: &Config,
+    params: HashMap<String, String>,
+    options: Option<&WriteOptions>,
+) -> Result<(R, WriteMeta)> {
+    let start = Instant::now();
+    let request = write_request(config, method, path, body, params, options)?;
+    let retry = options.map(|o| o.retry).unwrap_or(false);
+    execute(config, retry, request)?.into_write(start)
 }
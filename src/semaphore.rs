@@ -0,0 +1,409 @@
+//! Counting semaphore over a KV prefix, following the Consul semaphore recipe
+//! and the `SemaphoreOptions` of the Go API.
+//!
+//! Each contender holds a key named after its session under the prefix, and
+//! the `.lock` key under it lists the sessions holding a slot, updated with
+//! check-and-set writes.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use consul::semaphore::{Semaphore, SemaphoreOptions};
+//! use consul::{Client, Config};
+//!
+//! let client = Client::new(Config::new().unwrap());
+//! let semaphore = Semaphore::new(client, SemaphoreOptions::new("service/batch/slots", 3));
+//! if let Some(guard) = semaphore.acquire(Some(Duration::from_secs(30))).unwrap() {
+//!     // Work while holding a slot, checking `guard.lost()` regularly
+//!     assert!(!guard.lost().is_lost());
+//! } // The slot is released when the guard is dropped
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::errors::{Error, ErrorKind, Result};
+use crate::kv::{Codec, KVPair, KV};
+use crate::lock::{blocking_wait, remaining, LostSignal, SessionKeeper};
+use crate::watch::StopHandle;
+use crate::{Client, QueryOptions};
+
+/// Flags of the semaphore keys, telling them apart from the lock ones.
+pub const SEMAPHORE_FLAG_VALUE: u64 = 0xe0f6_9a2b_aa41_4de0;
+
+/// Name of the key listing the holders, under the prefix.
+pub const SEMAPHORE_LOCK_KEY: &str = ".lock";
+
+#[derive(Clone, Debug)]
+pub struct SemaphoreOptions {
+    pub prefix: String,
+    /// Number of slots, which must match the one of the current holders.
+    pub limit: usize,
+    /// Value of the contender key while waiting for or holding a slot.
+    pub value: Vec<u8>,
+    /// Session of the contender. When unset, a session is created with
+    /// `session_name` and `session_ttl`, renewed while the slot is held and
+    /// destroyed when it is released.
+    pub session: Option<String>,
+    pub session_name: String,
+    pub session_ttl: Duration,
+    /// Maximum duration of each blocking query waiting for a free slot.
+    pub wait_time: Duration,
+    /// Failed queries tolerated while monitoring the held slot, before
+    /// considering it lost.
+    pub monitor_retries: u32,
+    pub monitor_retry_time: Duration,
+}
+
+impl Default for SemaphoreOptions {
+    fn default() -> Self {
+        SemaphoreOptions {
+            prefix: String::new(),
+            limit: 1,
+            value: Vec::new(),
+            session: None,
+            session_name: String::from("Consul API Semaphore"),
+            session_ttl: Duration::from_secs(15),
+            wait_time: Duration::from_secs(15),
+            monitor_retries: 0,
+            monitor_retry_time: Duration::from_secs(2),
+        }
+    }
+}
+
+impl SemaphoreOptions {
+    pub fn new(prefix: &str, limit: usize) -> SemaphoreOptions {
+        SemaphoreOptions {
+            prefix: prefix.trim_end_matches('/').to_owned(),
+            limit,
+            ..Default::default()
+        }
+    }
+}
+
+/// Value of the `.lock` key.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SemaphoreLock {
+    Limit: usize,
+    Holders: HashMap<String, bool>,
+}
+
+/// A semaphore over a KV prefix, letting at most `limit` sessions hold a slot
+/// at a time.
+pub struct Semaphore {
+    client: Client,
+    options: SemaphoreOptions,
+    held: Arc<AtomicBool>,
+}
+
+impl Semaphore {
+    pub fn new(client: Client, options: SemaphoreOptions) -> Semaphore {
+        Semaphore {
+            client,
+            options,
+            held: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Waits for a slot, up to the timeout if any. Returns `None` when the
+    /// timeout elapsed before a slot could be acquired.
+    pub fn acquire(&self, timeout: Option<Duration>) -> Result<Option<SemaphoreGuard>> {
+        self.acquire_slot(timeout.map(|t| Instant::now() + t))
+    }
+
+    /// Acquires a slot only if one is free, without waiting.
+    pub fn try_acquire(&self) -> Result<Option<SemaphoreGuard>> {
+        self.acquire_slot(Some(Instant::now()))
+    }
+
+    fn lock_key(&self) -> String {
+        format!("{}/{}", self.options.prefix, SEMAPHORE_LOCK_KEY)
+    }
+
+    fn acquire_slot(&self, deadline: Option<Instant>) -> Result<Option<SemaphoreGuard>> {
+        if self.options.limit == 0 {
+            bail!(
+                "The limit of the semaphore on '{}' must be positive",
+                self.options.prefix
+            );
+        }
+        if self.held.swap(true, Ordering::SeqCst) {
+            bail!("The semaphore on '{}' is already held", self.options.prefix);
+        }
+        let (session, keeper) = match &self.options.session {
+            Some(session) => (session.clone(), None),
+            None => {
+                let created = SessionKeeper::create(
+                    &self.client,
+                    &self.options.session_name,
+                    self.options.session_ttl,
+                );
+                match created {
+                    Ok(keeper) => (keeper.id().to_owned(), Some(keeper)),
+                    Err(e) => {
+                        self.held.store(false, Ordering::SeqCst);
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        let contender = format!("{}/{}", self.options.prefix, session);
+        match self.acquire_with(&session, &contender, deadline) {
+            Ok(true) => {
+                let lost = LostSignal::default();
+                let stop = StopHandle::default();
+                self.monitor(&session, &lost, &stop);
+                Ok(Some(SemaphoreGuard {
+                    client: self.client.clone(),
+                    lock_key: self.lock_key(),
+                    contender,
+                    session,
+                    keeper,
+                    lost,
+                    stop,
+                    held: self.held.clone(),
+                    released: false,
+                }))
+            }
+            result => {
+                let _ = self.client.delete(&contender, None);
+                if let Some(keeper) = keeper {
+                    let _ = keeper.destroy();
+                }
+                self.held.store(false, Ordering::SeqCst);
+                result.map(|_| None)
+            }
+        }
+    }
+
+    /// Creates the contender key of the session and adds the session to the
+    /// holders once a slot is free, returning `false` once the deadline
+    /// passed.
+    fn acquire_with(
+        &self,
+        session: &str,
+        contender: &str,
+        deadline: Option<Instant>,
+    ) -> Result<bool> {
+        let options = &self.options;
+        let pair = KVPair {
+            Key: contender.to_owned(),
+            Value: options.value.clone(),
+            Flags: Some(SEMAPHORE_FLAG_VALUE),
+            Session: Some(session.to_owned()),
+            ..Default::default()
+        };
+        if !self.client.acquire(&pair, None)?.0 {
+            bail!("Failed to create the contender key '{}'", contender);
+        }
+
+        let lock_key = self.lock_key();
+        let mut wait_index = None;
+        loop {
+            let wait = remaining(deadline).map_or(options.wait_time, |r| r.min(options.wait_time));
+            if wait_index.is_some() && wait == Duration::from_secs(0) {
+                return Ok(false);
+            }
+            let query = QueryOptions {
+                wait_index,
+                wait_time: Some(blocking_wait(wait)),
+                ..Default::default()
+            };
+            let (pairs, meta) = self
+                .client
+                .list(&format!("{}/", options.prefix), Some(&query))?;
+            let (lock_pair, mut state) = self.decode(&pairs)?;
+            if state.Holders.contains_key(session) {
+                return Ok(true);
+            }
+            // Free the slots of the holders whose session is gone
+            let live: HashSet<&str> = pairs
+                .iter()
+                .filter(|p| p.Key != lock_key)
+                .filter_map(|p| p.Session.as_deref())
+                .collect();
+            state
+                .Holders
+                .retain(|holder, _| live.contains(holder.as_str()));
+            if state.Holders.len() >= options.limit {
+                wait_index = meta.last_index;
+                continue;
+            }
+
+            state.Holders.insert(session.to_owned(), true);
+            let pair = KVPair {
+                Key: lock_key.clone(),
+                Value: Codec::Json.encode(&lock_key, &state)?,
+                Flags: Some(SEMAPHORE_FLAG_VALUE),
+                ModifyIndex: Some(lock_pair.and_then(|p| p.ModifyIndex).unwrap_or(0)),
+                ..Default::default()
+            };
+            match self.client.put_cas(&pair, None) {
+                Ok(_) => return Ok(true),
+                // Another contender updated the holders meanwhile
+                Err(Error(ErrorKind::CasLost(_), _)) => wait_index = None,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Checks the keys under the prefix belong to a semaphore of the same
+    /// limit, returning its `.lock` key and holders.
+    fn decode<'a>(&self, pairs: &'a [KVPair]) -> Result<(Option<&'a KVPair>, SemaphoreLock)> {
+        let options = &self.options;
+        if pairs.iter().any(|p| p.Flags != Some(SEMAPHORE_FLAG_VALUE)) {
+            bail!("The prefix '{}' is not used as a semaphore", options.prefix);
+        }
+        let lock_key = self.lock_key();
+        let lock_pair = match pairs.iter().find(|p| p.Key == lock_key) {
+            Some(pair) => pair,
+            None => {
+                let state = SemaphoreLock {
+                    Limit: options.limit,
+                    ..Default::default()
+                };
+                return Ok((None, state));
+            }
+        };
+        let state = decode_lock(lock_pair)?;
+        if state.Limit != options.limit {
+            bail!(
+                "The limit {} of the semaphore on '{}' does not match the limit {} of its holders",
+                options.limit,
+                options.prefix,
+                state.Limit
+            );
+        }
+        Ok((Some(lock_pair), state))
+    }
+
+    /// Watches the keys of the semaphore in the background, signaling when
+    /// the session no longer holds its contender key or a slot.
+    fn monitor(&self, session: &str, lost: &LostSignal, stop: &StopHandle) {
+        let client = self.client.clone();
+        let options = self.options.clone();
+        let (contender, lock_key) = (format!("{}/{}", options.prefix, session), self.lock_key());
+        let (session, lost, stop) = (session.to_owned(), lost.clone(), stop.clone());
+        thread::spawn(move || {
+            let mut wait_index = None;
+            let mut retries = options.monitor_retries;
+            while !stop.is_stopped() {
+                let query = QueryOptions {
+                    wait_index,
+                    wait_time: Some(blocking_wait(options.wait_time)),
+                    ..Default::default()
+                };
+                match client.list(&format!("{}/", options.prefix), Some(&query)) {
+                    Ok((pairs, meta)) => {
+                        let contending = pairs
+                            .iter()
+                            .any(|p| p.Key == contender && p.Session.as_ref() == Some(&session));
+                        let holding = pairs
+                            .iter()
+                            .find(|p| p.Key == lock_key)
+                            .and_then(|p| decode_lock(p).ok())
+                            .is_some_and(|state| state.Holders.contains_key(&session));
+                        if !contending || !holding {
+                            break;
+                        }
+                        wait_index = meta.last_index;
+                        retries = options.monitor_retries;
+                    }
+                    Err(_) if retries > 0 => {
+                        retries -= 1;
+                        stop.sleep(options.monitor_retry_time);
+                    }
+                    Err(_) => break,
+                }
+            }
+            if !stop.is_stopped() {
+                lost.set();
+            }
+        });
+    }
+}
+
+fn decode_lock(pair: &KVPair) -> Result<SemaphoreLock> {
+    Codec::Json.decode(&pair.Key, &pair.Value)
+}
+
+/// A held slot of a semaphore, released when dropped.
+pub struct SemaphoreGuard {
+    client: Client,
+    lock_key: String,
+    contender: String,
+    session: String,
+    keeper: Option<SessionKeeper>,
+    lost: LostSignal,
+    stop: StopHandle,
+    held: Arc<AtomicBool>,
+    released: bool,
+}
+
+impl SemaphoreGuard {
+    /// Signal set if the slot is lost while held.
+    pub fn lost(&self) -> &LostSignal {
+        &self.lost
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Releases the slot, and destroys its session if the semaphore created
+    /// it.
+    pub fn release(mut self) -> Result<()> {
+        self.release_slot()
+    }
+
+    fn release_slot(&mut self) -> Result<()> {
+        if self.released {
+            return Ok(());
+        }
+        self.released = true;
+        self.stop.stop();
+        let removed = self.remove_holder();
+        let deleted = self.client.delete(&self.contender, None).map(|_| ());
+        let destroyed = match self.keeper.take() {
+            Some(keeper) => keeper.destroy(),
+            None => Ok(()),
+        };
+        self.held.store(false, Ordering::SeqCst);
+        removed.and(deleted).and(destroyed)
+    }
+
+    /// Removes the session from the holders, retrying when another contender
+    /// updated them meanwhile.
+    fn remove_holder(&self) -> Result<()> {
+        loop {
+            let pair = match self.client.get(&self.lock_key, None)?.0 {
+                Some(pair) => pair,
+                None => return Ok(()),
+            };
+            let mut state = decode_lock(&pair)?;
+            if state.Holders.remove(&self.session).is_none() {
+                return Ok(());
+            }
+            let pair = KVPair {
+                Value: Codec::Json.encode(&self.lock_key, &state)?,
+                ..pair
+            };
+            match self.client.put_cas(&pair, None) {
+                Ok(_) => return Ok(()),
+                Err(Error(ErrorKind::CasLost(_), _)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for SemaphoreGuard {
+    fn drop(&mut self) {
+        let _ = self.release_slot();
+    }
+}
@@ -1,7 +1,11 @@
 use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::errors::Result;
+use crate::errors::{Error, ErrorKind, Result};
 use crate::request::{get, put};
+use crate::watch::StopHandle;
 use crate::{Client, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
 
 #[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
@@ -22,6 +26,8 @@ pub struct SessionEntry {
     pub Behavior: Option<String>,
     pub Checks: Option<Vec<String>>,
     pub TTL: Option<String>,
+    pub Namespace: Option<String>,
+    pub Partition: Option<String>,
 }
 
 pub trait Session {
@@ -108,3 +114,147 @@ impl Session for Client {
         )
     }
 }
+
+/// Renews a session in the background, from `renew_periodic`. Renewal stops
+/// when the handle is stopped or dropped.
+pub struct RenewHandle {
+    client: Client,
+    id: String,
+    options: Option<WriteOptions>,
+    destroy_on_stop: bool,
+    stop: StopHandle,
+    gone: Receiver<Error>,
+    stopped: bool,
+}
+
+impl RenewHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Receives the error showing the session is gone, once Consul no longer
+    /// knows it (404) or it could not be renewed within its TTL. Renewal
+    /// stops then.
+    pub fn gone(&self) -> &Receiver<Error> {
+        &self.gone
+    }
+
+    /// Stops renewing, destroying the session if asked to.
+    pub fn stop(mut self) -> Result<()> {
+        self.stop_renewal()
+    }
+
+    fn stop_renewal(&mut self) -> Result<()> {
+        if self.stopped {
+            return Ok(());
+        }
+        self.stopped = true;
+        self.stop.stop();
+        if !self.destroy_on_stop {
+            return Ok(());
+        }
+        let options = self.options.as_ref();
+        self.client.destroy(&self.id, options).map(|_| ())
+    }
+}
+
+impl Drop for RenewHandle {
+    fn drop(&mut self) {
+        let _ = self.stop_renewal();
+    }
+}
+
+/// Renews the session in a background thread every half of its TTL, adapting
+/// to the TTL returned by Consul. Failed renewals are retried every second
+/// until the session expires, reported through `RenewHandle::gone`.
+pub fn renew_periodic(
+    client: &Client,
+    id: &str,
+    ttl: Duration,
+    destroy_on_stop: bool,
+    options: Option<&WriteOptions>,
+) -> Result<RenewHandle> {
+    if ttl == Duration::from_secs(0) {
+        bail!("The session '{}' has no TTL to renew", id);
+    }
+    let (sender, gone) = mpsc::channel();
+    let stop = StopHandle::default();
+    {
+        let (client, id, options) = (client.clone(), id.to_owned(), options.cloned());
+        let stop = stop.clone();
+        thread::spawn(move || renew_loop(&client, &id, ttl, options.as_ref(), &stop, &sender));
+    }
+    Ok(RenewHandle {
+        client: client.clone(),
+        id: id.to_owned(),
+        options: options.cloned(),
+        destroy_on_stop,
+        stop,
+        gone,
+        stopped: false,
+    })
+}
+
+fn renew_loop(
+    client: &Client,
+    id: &str,
+    mut ttl: Duration,
+    options: Option<&WriteOptions>,
+    stop: &StopHandle,
+    gone: &Sender<Error>,
+) {
+    let mut renewed = Instant::now();
+    let mut wait = ttl / 2;
+    while !stop.sleep(wait) {
+        match client.renew(id, options) {
+            Ok((entries, _)) => {
+                renewed = Instant::now();
+                let returned = entries.first().and_then(|e| e.TTL.as_deref());
+                if let Some(returned) = returned.and_then(parse_duration) {
+                    if returned > Duration::from_secs(0) {
+                        ttl = returned;
+                    }
+                }
+                wait = ttl / 2;
+            }
+            Err(e) => {
+                if matches!(e.kind(), ErrorKind::NotFound(_)) || renewed.elapsed() >= ttl {
+                    if !stop.is_stopped() {
+                        let _ = gone.send(e);
+                    }
+                    return;
+                }
+                wait = (ttl / 2).min(Duration::from_secs(1));
+            }
+        }
+    }
+}
+
+/// Parses a duration in the format of Go, such as `15s` or `1m30s`.
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    if s == "0" {
+        return Some(Duration::from_secs(0));
+    }
+    let mut rest = s;
+    let mut secs = 0.0;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let (value, tail) = rest.split_at(split);
+        let split = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(split);
+        let value: f64 = value.parse().ok()?;
+        secs += match unit {
+            "ns" => value / 1e9,
+            "us" | "µs" => value / 1e6,
+            "ms" => value / 1e3,
+            "s" => value,
+            "m" => value * 60.0,
+            "h" => value * 3600.0,
+            _ => return None,
+        };
+        rest = tail;
+    }
+    (!s.is_empty()).then(|| Duration::from_secs_f64(secs))
+}
@@ -0,0 +1,1135 @@
+//! In-memory fake of the Consul HTTP API, to run tests without an agent.
+//!
+//! `FakeConsul` serves the KV, session, catalog, health and agent endpoints
+//! used by this crate, and the transactions of KV operations, with the
+//! `X-Consul-Index` header and blocking queries, from a single node of the
+//! `dc1` datacenter also running the `consul` service.
+//!
+//! ```
+//! use consul::kv::KV;
+//! use consul::test_support::FakeConsul;
+//! use consul::Client;
+//!
+//! let consul = FakeConsul::start().unwrap();
+//! let client = Client::new(consul.config().unwrap());
+//! assert!(client.list("", None).unwrap().0.is_empty());
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Server};
+use url::Url;
+
+use crate::errors::Result;
+use crate::session::parse_duration;
+use crate::Config;
+
+const DATACENTER: &str = "dc1";
+const DEFAULT_WAIT_TIME: Duration = Duration::from_secs(300);
+const MAX_WAIT_TIME: Duration = Duration::from_secs(600);
+
+/// A fake agent listening on a random local port, stopped when dropped.
+pub struct FakeConsul {
+    address: String,
+    node: String,
+    server: Arc<Server>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl FakeConsul {
+    /// Starts a fake agent whose node is named `fake-consul`.
+    pub fn start() -> Result<FakeConsul> {
+        FakeConsul::start_with_node("fake-consul")
+    }
+
+    /// Starts a fake agent with the given node name, which sessions are
+    /// created on by default.
+    pub fn start_with_node(node: &str) -> Result<FakeConsul> {
+        let server = Server::http("127.0.0.1:0")
+            .map_err(|e| format!("Failed to start the fake Consul server: {}", e))?;
+        let server = Arc::new(server);
+        let address = match server.server_addr().to_ip() {
+            Some(addr) => format!("http://{}", addr),
+            None => bail!("The fake Consul server is not listening on TCP"),
+        };
+        let state = Arc::new(State {
+            node: node.to_owned(),
+            store: Mutex::new(Store::new(node)),
+            changed: Condvar::new(),
+        });
+        let handle = {
+            let server = server.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let state = state.clone();
+                    thread::spawn(move || state.handle(request));
+                }
+            })
+        };
+        Ok(FakeConsul {
+            address,
+            node: node.to_owned(),
+            server,
+            handle: Some(handle),
+        })
+    }
+
+    /// Address to set as `Config::address`.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+
+    /// A default `Config` pointing at this agent.
+    pub fn config(&self) -> Result<Config> {
+        let mut config = Config::new()?;
+        config.address = self.address.clone();
+        Ok(config)
+    }
+}
+
+impl Drop for FakeConsul {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Tables of the store, each with its own index for blocking queries.
+#[derive(Clone, Copy)]
+enum Table {
+    Kv,
+    Sessions,
+    Catalog,
+}
+
+#[derive(Clone)]
+struct KvEntry {
+    value: Vec<u8>,
+    flags: u64,
+    create_index: u64,
+    modify_index: u64,
+    lock_index: u64,
+    session: Option<String>,
+}
+
+struct SessionRecord {
+    entry: Value,
+    behavior: String,
+    expires: Option<(Instant, Duration)>,
+}
+
+struct NodeRecord {
+    id: String,
+    address: String,
+    tagged_addresses: Value,
+    meta: Value,
+    create_index: u64,
+    modify_index: u64,
+    services: BTreeMap<String, Value>,
+    checks: BTreeMap<String, Value>,
+}
+
+struct Store {
+    index: u64,
+    table_indexes: [u64; 3],
+    kv: BTreeMap<String, KvEntry>,
+    sessions: BTreeMap<String, SessionRecord>,
+    nodes: BTreeMap<String, NodeRecord>,
+}
+
+/// A response of the fake agent.
+struct Reply {
+    status: u16,
+    body: Vec<u8>,
+    index: Option<u64>,
+}
+
+impl Reply {
+    fn json(value: &Value) -> Reply {
+        Reply {
+            status: 200,
+            body: value.to_string().into_bytes(),
+            index: None,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Reply {
+        Reply {
+            status,
+            body: message.as_bytes().to_vec(),
+            index: None,
+        }
+    }
+
+    fn with_index(mut self, index: u64) -> Reply {
+        self.index = Some(index.max(1));
+        self
+    }
+}
+
+struct State {
+    node: String,
+    store: Mutex<Store>,
+    changed: Condvar,
+}
+
+impl State {
+    fn handle(&self, mut request: Request) {
+        let mut body = Vec::new();
+        let reply = match request.as_reader().read_to_end(&mut body) {
+            Ok(_) => self.route(request.method(), request.url(), &body),
+            Err(e) => Reply::error(400, &e.to_string()),
+        };
+        let mut response = tiny_http::Response::from_data(reply.body)
+            .with_status_code(reply.status)
+            .with_header(header("Content-Type", "application/json"));
+        if let Some(index) = reply.index {
+            response = response
+                .with_header(header("X-Consul-Index", &index.to_string()))
+                .with_header(header("X-Consul-KnownLeader", "true"))
+                .with_header(header("X-Consul-LastContact", "0"));
+        }
+        let _ = request.respond(response);
+    }
+
+    fn route(&self, method: &Method, url: &str, body: &[u8]) -> Reply {
+        let url = match Url::parse(&format!("http://localhost{}", url)) {
+            Ok(url) => url,
+            Err(e) => return Reply::error(400, &e.to_string()),
+        };
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        let path = percent_decode(url.path());
+        let (get, put, delete) = (
+            *method == Method::Get,
+            *method == Method::Put,
+            *method == Method::Delete,
+        );
+
+        if let Some(key) = path.strip_prefix("/v1/kv/") {
+            return match method {
+                Method::Get => self.kv_get(key, &params),
+                Method::Put => self.kv_put(key, &params, body),
+                Method::Delete => self.kv_delete(key, &params),
+                _ => Reply::error(405, "method not allowed"),
+            };
+        }
+        let reply = match path.as_str() {
+            "/v1/txn" if put => Some(self.txn(body)),
+            "/v1/session/create" if put => Some(self.session_create(body)),
+            "/v1/session/list" if get => Some(self.session_list(&params, |_| true)),
+            "/v1/catalog/register" if put => Some(self.catalog_register(body)),
+            "/v1/catalog/deregister" if put => Some(self.catalog_deregister(body)),
+            "/v1/catalog/datacenters" if get => Some(Reply::json(&json!([DATACENTER]))),
+            "/v1/catalog/nodes" if get => Some(self.catalog_nodes(&params)),
+            "/v1/catalog/services" if get => Some(self.catalog_services(&params)),
+            "/v1/agent/services" if get => Some(self.agent_services(&params)),
+            "/v1/agent/checks" if get => Some(self.agent_checks()),
+            "/v1/agent/members" if get => Some(self.agent_members()),
+            "/v1/agent/reload"
+            | "/v1/agent/maintenance"
+            | "/v1/agent/leave"
+            | "/v1/agent/force-leave"
+                if put =>
+            {
+                Some(Reply::json(&Value::Null))
+            }
+            _ => None,
+        };
+        if let Some(reply) = reply {
+            return reply;
+        }
+        let reply = if let Some(id) = path.strip_prefix("/v1/session/destroy/") {
+            (put || delete).then(|| self.session_destroy(id))
+        } else if let Some(id) = path.strip_prefix("/v1/session/info/") {
+            get.then(|| self.session_list(&params, |s| s["ID"] == id))
+        } else if let Some(node) = path.strip_prefix("/v1/session/node/") {
+            get.then(|| self.session_list(&params, |s| s["Node"] == node))
+        } else if let Some(id) = path.strip_prefix("/v1/session/renew/") {
+            put.then(|| self.session_renew(id))
+        } else if let Some(service) = path.strip_prefix("/v1/health/service/") {
+            get.then(|| self.health_service(service, &params))
+        } else if path.starts_with("/v1/agent/join/") {
+            put.then(|| Reply::json(&Value::Null))
+        } else {
+            None
+        };
+        reply.unwrap_or_else(|| Reply::error(404, &format!("no handler for {} {}", method, path)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Store> {
+        let mut store = self.store.lock().unwrap_or_else(|e| e.into_inner());
+        if store.expire_sessions() {
+            self.changed.notify_all();
+        }
+        store
+    }
+
+    /// Locks the store once the table index is past the `index` parameter,
+    /// or the `wait` parameter elapsed.
+    fn wait(&self, table: Table, params: &HashMap<String, String>) -> MutexGuard<'_, Store> {
+        let mut store = self.lock();
+        let index = params.get("index").and_then(|i| i.parse::<u64>().ok());
+        let index = match index {
+            Some(index) if index > 0 => index,
+            _ => return store,
+        };
+        let wait = params
+            .get("wait")
+            .and_then(|w| parse_duration(w))
+            .unwrap_or(DEFAULT_WAIT_TIME)
+            .min(MAX_WAIT_TIME);
+        let deadline = Instant::now() + wait;
+        while store.table_index(table) <= index {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            // Wake up regularly to expire the sessions
+            let timeout = (deadline - now).min(Duration::from_secs(1));
+            store = self
+                .changed
+                .wait_timeout(store, timeout)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+            if store.expire_sessions() {
+                self.changed.notify_all();
+            }
+        }
+        store
+    }
+
+    fn kv_get(&self, key: &str, params: &HashMap<String, String>) -> Reply {
+        let store = self.wait(Table::Kv, params);
+        let index = store.table_index(Table::Kv);
+        if params.contains_key("raw") {
+            return match store.kv.get(key) {
+                Some(entry) => Reply {
+                    status: 200,
+                    body: entry.value.clone(),
+                    index: None,
+                },
+                None => Reply::error(404, ""),
+            }
+            .with_index(index);
+        }
+        if params.contains_key("keys") {
+            let separator = params.get("separator").filter(|s| !s.is_empty());
+            let keys: BTreeSet<&str> = store
+                .kv
+                .range(key.to_owned()..)
+                .take_while(|(k, _)| k.starts_with(key))
+                .map(|(k, _)| match separator {
+                    // Folders end at the first separator following the prefix
+                    Some(separator) => match k[key.len()..].find(separator.as_str()) {
+                        Some(at) => &k[..key.len() + at + separator.len()],
+                        None => k.as_str(),
+                    },
+                    None => k.as_str(),
+                })
+                .collect();
+            if keys.is_empty() {
+                return Reply::error(404, "").with_index(index);
+            }
+            return Reply::json(&json!(keys)).with_index(index);
+        }
+        let entries: Vec<Value> = if params.contains_key("recurse") {
+            store
+                .kv
+                .range(key.to_owned()..)
+                .take_while(|(k, _)| k.starts_with(key))
+                .map(|(k, e)| kv_json(k, e))
+                .collect()
+        } else {
+            store
+                .kv
+                .get(key)
+                .map(|e| kv_json(key, e))
+                .into_iter()
+                .collect()
+        };
+        if entries.is_empty() {
+            return Reply::error(404, "").with_index(index);
+        }
+        Reply::json(&Value::Array(entries)).with_index(index)
+    }
+
+    fn kv_put(&self, key: &str, params: &HashMap<String, String>, body: &[u8]) -> Reply {
+        let mut store = self.lock();
+        let flags = match params.get("flags").map(|f| f.parse::<u64>()) {
+            Some(Ok(flags)) => flags,
+            Some(Err(_)) => return Reply::error(400, "Invalid flags"),
+            None => store.kv.get(key).map(|e| e.flags).unwrap_or(0),
+        };
+        if let Some(reply) = check_cas(&store, key, params) {
+            return reply;
+        }
+        let current_session = store.kv.get(key).and_then(|e| e.session.clone());
+        let session = if let Some(session) = params.get("acquire") {
+            if !store.sessions.contains_key(session) {
+                return Reply::error(500, &format!("invalid session \"{}\"", session));
+            }
+            match current_session {
+                Some(ref holder) if holder != session => return Reply::json(&json!(false)),
+                _ => Some(session.to_owned()),
+            }
+        } else if let Some(session) = params.get("release") {
+            if current_session.as_ref() != Some(session) {
+                return Reply::json(&json!(false));
+            }
+            None
+        } else {
+            current_session.clone()
+        };
+        let index = store.bump(Table::Kv);
+        let acquired = session.is_some() && current_session.is_none();
+        let entry = store.kv.entry(key.to_owned()).or_insert(KvEntry {
+            value: Vec::new(),
+            flags,
+            create_index: index,
+            modify_index: index,
+            lock_index: 0,
+            session: None,
+        });
+        entry.value = body.to_vec();
+        entry.flags = flags;
+        entry.modify_index = index;
+        entry.session = session;
+        if acquired {
+            entry.lock_index += 1;
+        }
+        self.changed.notify_all();
+        Reply::json(&json!(true))
+    }
+
+    fn kv_delete(&self, key: &str, params: &HashMap<String, String>) -> Reply {
+        let mut store = self.lock();
+        if let Some(reply) = check_cas(&store, key, params) {
+            return reply;
+        }
+        let keys: Vec<String> = if params.contains_key("recurse") {
+            store
+                .kv
+                .range(key.to_owned()..)
+                .take_while(|(k, _)| k.starts_with(key))
+                .map(|(k, _)| k.clone())
+                .collect()
+        } else {
+            store
+                .kv
+                .get_key_value(key)
+                .map(|(k, _)| k.clone())
+                .into_iter()
+                .collect()
+        };
+        for k in keys {
+            store.kv.remove(&k);
+        }
+        store.bump(Table::Kv);
+        self.changed.notify_all();
+        Reply::json(&json!(true))
+    }
+
+    /// Applies the KV operations of a transaction to a copy of the store,
+    /// kept only if they all succeed.
+    fn txn(&self, body: &[u8]) -> Reply {
+        let ops: Vec<Value> = match serde_json::from_slice(body) {
+            Ok(ops) => ops,
+            Err(e) => return Reply::error(400, &format!("Failed to parse body: {}", e)),
+        };
+        let mut store = self.lock();
+        let mut kv = store.kv.clone();
+        let index = store.index + 1;
+        let mut results = Vec::new();
+        let mut errors = Vec::new();
+        let mut written = false;
+        for (i, op) in ops.iter().enumerate() {
+            let outcome = match op.get("KV") {
+                Some(op) => txn_kv(&mut kv, &store.sessions, index, op),
+                None => Err(String::from("operation not supported by the fake agent")),
+            };
+            match outcome {
+                Ok((entries, write)) => {
+                    results.extend(entries);
+                    written |= write;
+                }
+                Err(what) => errors.push(json!({ "OpIndex": i, "What": what })),
+            }
+        }
+        if !errors.is_empty() {
+            return Reply {
+                status: 409,
+                body: json!({ "Results": null, "Errors": errors })
+                    .to_string()
+                    .into_bytes(),
+                index: None,
+            };
+        }
+        if written {
+            store.bump(Table::Kv);
+            store.kv = kv;
+            self.changed.notify_all();
+        }
+        Reply::json(&json!({ "Results": results, "Errors": null })).with_index(store.index)
+    }
+
+    fn session_create(&self, body: &[u8]) -> Reply {
+        let request: Value = if body.is_empty() {
+            json!({})
+        } else {
+            match serde_json::from_slice(body) {
+                Ok(request) => request,
+                Err(e) => return Reply::error(400, &format!("Request decode failed: {}", e)),
+            }
+        };
+        let node = request["Node"].as_str().unwrap_or(&self.node).to_owned();
+        let ttl = match request["TTL"].as_str().filter(|t| !t.is_empty()) {
+            Some(ttl) => match parse_duration(ttl) {
+                Some(ttl) => Some(ttl),
+                None => return Reply::error(400, &format!("Invalid TTL '{}'", ttl)),
+            },
+            None => None,
+        };
+        let lock_delay = match &request["LockDelay"] {
+            Value::Number(n) => n.as_u64().unwrap_or(0),
+            Value::String(s) => parse_duration(s).map(|d| d.as_nanos() as u64).unwrap_or(0),
+            _ => Duration::from_secs(15).as_nanos() as u64,
+        };
+        let behavior = request["Behavior"]
+            .as_str()
+            .filter(|b| !b.is_empty())
+            .unwrap_or("release")
+            .to_owned();
+        let checks = match &request["Checks"] {
+            Value::Null => json!(["serfHealth"]),
+            checks => checks.clone(),
+        };
+
+        let mut store = self.lock();
+        if !store.nodes.contains_key(&node) {
+            return Reply::error(500, "Missing node registration");
+        }
+        let index = store.bump(Table::Sessions);
+        let id = uuid();
+        let entry = json!({
+            "ID": id,
+            "Name": request["Name"].as_str().unwrap_or_default(),
+            "Node": node,
+            "Checks": checks,
+            "LockDelay": lock_delay,
+            "Behavior": behavior,
+            "TTL": request["TTL"].as_str().unwrap_or_default(),
+            "CreateIndex": index,
+            "ModifyIndex": index,
+        });
+        store.sessions.insert(
+            id.clone(),
+            SessionRecord {
+                entry,
+                behavior,
+                expires: ttl.map(|ttl| (Instant::now() + ttl, ttl)),
+            },
+        );
+        self.changed.notify_all();
+        Reply::json(&json!({ "ID": id }))
+    }
+
+    fn session_destroy(&self, id: &str) -> Reply {
+        let mut store = self.lock();
+        store.invalidate_session(id);
+        self.changed.notify_all();
+        Reply::json(&json!(true))
+    }
+
+    fn session_renew(&self, id: &str) -> Reply {
+        let mut store = self.lock();
+        match store.sessions.get_mut(id) {
+            Some(session) => {
+                if let Some((ref mut expires, ttl)) = session.expires {
+                    *expires = Instant::now() + ttl;
+                }
+                Reply::json(&json!([session.entry]))
+            }
+            None => Reply::error(404, &format!("Session id '{}' not found", id)),
+        }
+    }
+
+    fn session_list<F>(&self, params: &HashMap<String, String>, filter: F) -> Reply
+    where
+        F: Fn(&Value) -> bool,
+    {
+        let store = self.wait(Table::Sessions, params);
+        let sessions: Vec<Value> = store
+            .sessions
+            .values()
+            .map(|s| s.entry.clone())
+            .filter(|s| filter(s))
+            .collect();
+        Reply::json(&Value::Array(sessions)).with_index(store.table_index(Table::Sessions))
+    }
+
+    fn catalog_register(&self, body: &[u8]) -> Reply {
+        let request: Value = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => return Reply::error(400, &format!("Request decode failed: {}", e)),
+        };
+        let node = match request["Node"].as_str().filter(|n| !n.is_empty()) {
+            Some(node) => node.to_owned(),
+            None => return Reply::error(400, "Must provide node"),
+        };
+        let address = match request["Address"].as_str().filter(|a| !a.is_empty()) {
+            Some(address) => address.to_owned(),
+            None => return Reply::error(400, "Must provide address"),
+        };
+        let mut store = self.lock();
+        let index = store.bump(Table::Catalog);
+        let record = store.nodes.entry(node.clone()).or_insert(NodeRecord {
+            id: String::new(),
+            address: String::new(),
+            tagged_addresses: json!({}),
+            meta: json!({}),
+            create_index: index,
+            modify_index: index,
+            services: BTreeMap::new(),
+            checks: BTreeMap::new(),
+        });
+        if !request["SkipNodeUpdate"].as_bool().unwrap_or(false) {
+            record.id = request["ID"].as_str().unwrap_or_default().to_owned();
+            record.address = address;
+            record.tagged_addresses = object_or_empty(&request["TaggedAddresses"]);
+            record.meta = object_or_empty(&request["NodeMeta"]);
+        }
+        record.modify_index = index;
+        if request["Service"].is_object() {
+            let service = &request["Service"];
+            let name = service["Service"].as_str().unwrap_or_default();
+            let id = service["ID"]
+                .as_str()
+                .filter(|id| !id.is_empty())
+                .unwrap_or(name)
+                .to_owned();
+            let create_index = record
+                .services
+                .get(&id)
+                .and_then(|s| s["CreateIndex"].as_u64())
+                .unwrap_or(index);
+            record
+                .services
+                .insert(id.clone(), service_json(&id, service, create_index, index));
+        }
+        let checks = match &request["Checks"] {
+            Value::Array(checks) => checks.clone(),
+            _ => Vec::new(),
+        };
+        for check in request.get("Check").into_iter().chain(checks.iter()) {
+            if !check.is_object() {
+                continue;
+            }
+            let check = check_json(&node, check, record, index);
+            let id = check["CheckID"].as_str().unwrap_or_default().to_owned();
+            record.checks.insert(id, check);
+        }
+        self.changed.notify_all();
+        Reply::json(&json!(true))
+    }
+
+    fn catalog_deregister(&self, body: &[u8]) -> Reply {
+        let request: Value = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => return Reply::error(400, &format!("Request decode failed: {}", e)),
+        };
+        let node = request["Node"].as_str().unwrap_or_default();
+        let service_id = request["ServiceID"].as_str().filter(|s| !s.is_empty());
+        let check_id = request["CheckID"].as_str().filter(|c| !c.is_empty());
+        let mut store = self.lock();
+        store.bump(Table::Catalog);
+        match (service_id, check_id) {
+            (Some(service_id), _) => {
+                if let Some(record) = store.nodes.get_mut(node) {
+                    record.services.remove(service_id);
+                    record
+                        .checks
+                        .retain(|_, check| check["ServiceID"] != service_id);
+                }
+            }
+            (None, Some(check_id)) => {
+                if let Some(record) = store.nodes.get_mut(node) {
+                    record.checks.remove(check_id);
+                }
+            }
+            (None, None) => {
+                store.nodes.remove(node);
+            }
+        }
+        self.changed.notify_all();
+        Reply::json(&json!(true))
+    }
+
+    fn catalog_nodes(&self, params: &HashMap<String, String>) -> Reply {
+        let store = self.wait(Table::Catalog, params);
+        let nodes: Vec<Value> = store
+            .nodes
+            .iter()
+            .map(|(name, record)| node_json(name, record))
+            .collect();
+        Reply::json(&Value::Array(nodes)).with_index(store.table_index(Table::Catalog))
+    }
+
+    fn catalog_services(&self, params: &HashMap<String, String>) -> Reply {
+        let store = self.wait(Table::Catalog, params);
+        let mut services: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for service in store.nodes.values().flat_map(|n| n.services.values()) {
+            let tags = services
+                .entry(service["Service"].as_str().unwrap_or_default().to_owned())
+                .or_default();
+            if let Some(service_tags) = service["Tags"].as_array() {
+                tags.extend(
+                    service_tags
+                        .iter()
+                        .filter_map(|t| t.as_str())
+                        .map(String::from),
+                );
+            }
+        }
+        Reply::json(&json!(services)).with_index(store.table_index(Table::Catalog))
+    }
+
+    fn health_service(&self, service: &str, params: &HashMap<String, String>) -> Reply {
+        let store = self.wait(Table::Catalog, params);
+        let passing = params.contains_key("passing");
+        let tag = params.get("tag");
+        let mut entries = Vec::new();
+        for (name, record) in &store.nodes {
+            for (id, instance) in &record.services {
+                if instance["Service"] != service {
+                    continue;
+                }
+                if let Some(tag) = tag {
+                    let tags = instance["Tags"].as_array();
+                    if !tags.map(|t| t.iter().any(|t| t == tag)).unwrap_or(false) {
+                        continue;
+                    }
+                }
+                let checks: Vec<Value> = record
+                    .checks
+                    .values()
+                    .filter(|c| c["ServiceID"] == "" || c["ServiceID"] == id.as_str())
+                    .cloned()
+                    .collect();
+                if passing && checks.iter().any(|c| c["Status"] != "passing") {
+                    continue;
+                }
+                entries.push(json!({
+                    "Node": node_json(name, record),
+                    "Service": instance,
+                    "Checks": checks,
+                }));
+            }
+        }
+        Reply::json(&Value::Array(entries)).with_index(store.table_index(Table::Catalog))
+    }
+
+    fn agent_services(&self, params: &HashMap<String, String>) -> Reply {
+        let store = self.wait(Table::Catalog, params);
+        let services = store
+            .nodes
+            .get(&self.node)
+            .map(|n| json!(n.services))
+            .unwrap_or_else(|| json!({}));
+        Reply::json(&services).with_index(store.table_index(Table::Catalog))
+    }
+
+    fn agent_checks(&self) -> Reply {
+        let store = self.lock();
+        let checks = store
+            .nodes
+            .get(&self.node)
+            .map(|n| json!(n.checks))
+            .unwrap_or_else(|| json!({}));
+        Reply::json(&checks)
+    }
+
+    fn agent_members(&self) -> Reply {
+        Reply::json(&json!([{
+            "Name": self.node,
+            "Addr": "127.0.0.1",
+            "Port": 8301,
+            "Tags": { "dc": DATACENTER, "role": "consul" },
+            "Status": 1,
+            "ProtocolMin": 1,
+            "ProtocolMax": 5,
+            "ProtocolCur": 2,
+            "DelegateMin": 2,
+            "DelegateMax": 5,
+            "DelegateCur": 4,
+        }]))
+    }
+}
+
+impl Store {
+    /// A store holding the agent node, running the `consul` service.
+    fn new(node: &str) -> Store {
+        let mut store = Store {
+            index: 0,
+            table_indexes: [0; 3],
+            kv: BTreeMap::new(),
+            sessions: BTreeMap::new(),
+            nodes: BTreeMap::new(),
+        };
+        let index = store.bump(Table::Catalog);
+        let mut record = NodeRecord {
+            id: uuid(),
+            address: String::from("127.0.0.1"),
+            tagged_addresses: json!({ "lan": "127.0.0.1", "wan": "127.0.0.1" }),
+            meta: json!({}),
+            create_index: index,
+            modify_index: index,
+            services: BTreeMap::new(),
+            checks: BTreeMap::new(),
+        };
+        let consul = json!({ "Service": "consul", "Port": 8300 });
+        record.services.insert(
+            String::from("consul"),
+            service_json("consul", &consul, index, index),
+        );
+        let serf =
+            json!({ "CheckID": "serfHealth", "Name": "Serf Health Status", "Status": "passing" });
+        let serf = check_json(node, &serf, &record, index);
+        record.checks.insert(String::from("serfHealth"), serf);
+        store.nodes.insert(node.to_owned(), record);
+        store
+    }
+
+    fn table_index(&self, table: Table) -> u64 {
+        self.table_indexes[table as usize]
+    }
+
+    /// Allocates the index of a write to the given table.
+    fn bump(&mut self, table: Table) -> u64 {
+        self.index += 1;
+        self.table_indexes[table as usize] = self.index;
+        self.index
+    }
+
+    /// Destroys a session, releasing or deleting the keys it holds according
+    /// to its behavior.
+    fn invalidate_session(&mut self, id: &str) {
+        let session = match self.sessions.remove(id) {
+            Some(session) => session,
+            None => return,
+        };
+        self.bump(Table::Sessions);
+        let held: Vec<String> = self
+            .kv
+            .iter()
+            .filter(|(_, e)| e.session.as_deref() == Some(id))
+            .map(|(k, _)| k.clone())
+            .collect();
+        if held.is_empty() {
+            return;
+        }
+        let index = self.bump(Table::Kv);
+        for key in held {
+            if session.behavior == "delete" {
+                self.kv.remove(&key);
+            } else if let Some(entry) = self.kv.get_mut(&key) {
+                entry.session = None;
+                entry.modify_index = index;
+            }
+        }
+    }
+
+    /// Destroys the sessions whose TTL elapsed, returning whether any was.
+    fn expire_sessions(&mut self) -> bool {
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| matches!(s.expires, Some((expires, _)) if expires <= now))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            self.invalidate_session(id);
+        }
+        !expired.is_empty()
+    }
+}
+
+/// Applies a KV operation of a transaction written at `index`, returning its
+/// results and whether it modified the store, or why it failed.
+fn txn_kv(
+    kv: &mut BTreeMap<String, KvEntry>,
+    sessions: &BTreeMap<String, SessionRecord>,
+    index: u64,
+    op: &Value,
+) -> std::result::Result<(Vec<Value>, bool), String> {
+    let verb = op["Verb"].as_str().unwrap_or_default();
+    let key = op["Key"].as_str().unwrap_or_default().to_owned();
+    let value = match op["Value"].as_str() {
+        Some(value) => base64::decode(value).map_err(|e| e.to_string())?,
+        None => Vec::new(),
+    };
+    let flags = op["Flags"].as_u64().unwrap_or(0);
+    let op_index = op["Index"].as_u64().unwrap_or(0);
+    let session = op["Session"].as_str().filter(|s| !s.is_empty());
+    let current = kv.get(&key);
+    // Results of the writes and checks, without the value
+    let entry = |kv: &BTreeMap<String, KvEntry>| {
+        let mut entry = kv_json(&key, &kv[&key]);
+        entry["Value"] = Value::Null;
+        vec![json!({ "KV": entry })]
+    };
+
+    match verb {
+        "set" | "cas" | "lock" | "unlock" => {
+            let holder = current.and_then(|e| e.session.clone());
+            let (session, acquired) = match verb {
+                "cas" if current.map_or(0, |e| e.modify_index) != op_index => {
+                    return Err(format!("failed to set key {:?}, index is stale", key));
+                }
+                "lock" => match session {
+                    Some(s) if !sessions.contains_key(s) => {
+                        return Err(format!("failed to lock key {:?}: invalid session", key));
+                    }
+                    Some(s) if holder.is_none() => (Some(s.to_owned()), true),
+                    Some(s) if holder.as_deref() == Some(s) => (holder, false),
+                    _ => {
+                        return Err(format!(
+                            "failed to lock key {:?}, lock is already held",
+                            key
+                        ))
+                    }
+                },
+                "unlock" => match session {
+                    Some(s) if holder.as_deref() == Some(s) => (None, false),
+                    _ => return Err(format!("failed to unlock key {:?}, lock isn't held", key)),
+                },
+                _ => (holder, false),
+            };
+            let entry_mut = kv.entry(key.clone()).or_insert(KvEntry {
+                value: Vec::new(),
+                flags,
+                create_index: index,
+                modify_index: index,
+                lock_index: 0,
+                session: None,
+            });
+            entry_mut.value = value;
+            entry_mut.flags = flags;
+            entry_mut.modify_index = index;
+            entry_mut.session = session;
+            if acquired {
+                entry_mut.lock_index += 1;
+            }
+            Ok((entry(kv), true))
+        }
+        "get" => match current {
+            Some(e) => Ok((vec![json!({ "KV": kv_json(&key, e) })], false)),
+            None => Err(format!("key {:?} doesn't exist", key)),
+        },
+        "get-tree" => {
+            let entries = kv
+                .range(key.clone()..)
+                .take_while(|(k, _)| k.starts_with(&key))
+                .map(|(k, e)| json!({ "KV": kv_json(k, e) }))
+                .collect();
+            Ok((entries, false))
+        }
+        "check-index" => match current {
+            Some(e) if e.modify_index == op_index => Ok((entry(kv), false)),
+            Some(e) => Err(format!(
+                "current modify index {} != {}",
+                e.modify_index, op_index
+            )),
+            None => Err(format!("key {:?} doesn't exist", key)),
+        },
+        "check-session" => match current {
+            Some(e) if e.session.is_some() && e.session.as_deref() == session => {
+                Ok((entry(kv), false))
+            }
+            Some(_) => Err(format!("key {:?} is not locked by the session", key)),
+            None => Err(format!("key {:?} doesn't exist", key)),
+        },
+        "check-not-exists" => match current {
+            Some(_) => Err(format!("key {:?} exists", key)),
+            None => Ok((Vec::new(), false)),
+        },
+        "delete" => Ok((Vec::new(), kv.remove(&key).is_some())),
+        "delete-tree" => {
+            let keys: Vec<String> = kv
+                .range(key.clone()..)
+                .take_while(|(k, _)| k.starts_with(&key))
+                .map(|(k, _)| k.clone())
+                .collect();
+            for k in &keys {
+                kv.remove(k);
+            }
+            Ok((Vec::new(), !keys.is_empty()))
+        }
+        // Like Consul, succeeds when the key does not exist
+        "delete-cas" => match current {
+            Some(e) if e.modify_index == op_index => {
+                kv.remove(&key);
+                Ok((Vec::new(), true))
+            }
+            Some(_) => Err(format!("failed to delete key {:?}, index is stale", key)),
+            None => Ok((Vec::new(), false)),
+        },
+        _ => Err(format!("unknown KV verb {:?}", verb)),
+    }
+}
+
+/// Applies the `cas` parameter of a KV write, returning `false` when the
+/// index does not match.
+fn check_cas(store: &Store, key: &str, params: &HashMap<String, String>) -> Option<Reply> {
+    let cas = match params.get("cas").map(|c| c.parse::<u64>()) {
+        Some(Ok(cas)) => cas,
+        Some(Err(_)) => return Some(Reply::error(400, "Invalid cas index")),
+        None => return None,
+    };
+    let current = store.kv.get(key).map(|e| e.modify_index);
+    let matches = match current {
+        Some(index) => index == cas,
+        None => cas == 0,
+    };
+    if matches {
+        None
+    } else {
+        Some(Reply::json(&json!(false)))
+    }
+}
+
+fn kv_json(key: &str, entry: &KvEntry) -> Value {
+    let mut value = json!({
+        "Key": key,
+        "CreateIndex": entry.create_index,
+        "ModifyIndex": entry.modify_index,
+        "LockIndex": entry.lock_index,
+        "Flags": entry.flags,
+        "Value": if entry.value.is_empty() {
+            Value::Null
+        } else {
+            Value::String(base64::encode(&entry.value))
+        },
+    });
+    if let Some(ref session) = entry.session {
+        value["Session"] = json!(session);
+    }
+    value
+}
+
+fn node_json(name: &str, record: &NodeRecord) -> Value {
+    json!({
+        "ID": record.id,
+        "Node": name,
+        "Address": record.address,
+        "Datacenter": DATACENTER,
+        "TaggedAddresses": record.tagged_addresses,
+        "Meta": record.meta,
+        "CreateIndex": record.create_index,
+        "ModifyIndex": record.modify_index,
+    })
+}
+
+fn service_json(id: &str, service: &Value, create_index: u64, modify_index: u64) -> Value {
+    json!({
+        "ID": id,
+        "Service": service["Service"],
+        "Tags": match &service["Tags"] {
+            Value::Array(tags) => Value::Array(tags.clone()),
+            _ => json!([]),
+        },
+        "Address": service["Address"].as_str().unwrap_or_default(),
+        "Meta": object_or_empty(&service["Meta"]),
+        "Port": service["Port"].as_u64().unwrap_or(0),
+        "EnableTagOverride": service["EnableTagOverride"].as_bool().unwrap_or(false),
+        "CreateIndex": create_index,
+        "ModifyIndex": modify_index,
+    })
+}
+
+fn check_json(node: &str, check: &Value, record: &NodeRecord, index: u64) -> Value {
+    let service_id = check["ServiceID"].as_str().unwrap_or_default();
+    let service_name = record
+        .services
+        .get(service_id)
+        .and_then(|s| s["Service"].as_str())
+        .unwrap_or_default();
+    let name = check["Name"].as_str().unwrap_or_default();
+    let id = check["CheckID"]
+        .as_str()
+        .filter(|id| !id.is_empty())
+        .unwrap_or(name);
+    json!({
+        "Node": node,
+        "CheckID": id,
+        "Name": name,
+        "Status": check["Status"].as_str().filter(|s| !s.is_empty()).unwrap_or("critical"),
+        "Notes": check["Notes"].as_str().unwrap_or_default(),
+        "Output": check["Output"].as_str().unwrap_or_default(),
+        "ServiceID": service_id,
+        "ServiceName": service_name,
+        "ServiceTags": record.services.get(service_id).map(|s| s["Tags"].clone()).unwrap_or_else(|| json!([])),
+        "CreateIndex": index,
+        "ModifyIndex": index,
+    })
+}
+
+fn object_or_empty(value: &Value) -> Value {
+    if value.is_object() {
+        value.clone()
+    } else {
+        json!({})
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+/// Decodes the `%XX` escapes of a URL path or query component.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn uuid() -> String {
+    let bytes: [u8; 16] = rand::thread_rng().gen();
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
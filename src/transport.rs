@@ -0,0 +1,97 @@
+//! HTTP transport used to send the requests to Consul.
+//!
+//! By default, requests go through the reqwest clients of the `Config`, or
+//! over a Unix domain socket for `unix://` addresses. Setting
+//! `Config::transport` replaces them, e.g. with a scripted transport to test
+//! code using the API traits without a Consul agent.
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+#[cfg(feature = "async")]
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode};
+use url::Url;
+
+use crate::errors::{ErrorKind, Result, ResultExt};
+
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    pub body: Option<Vec<u8>>,
+    /// Set on blocking queries, which may last longer than usual requests.
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Sends the requests of the blocking `Client`.
+///
+/// Failures to get a response should be reported as `ErrorKind::Transport`,
+/// non-2xx responses being handled by the caller.
+pub trait Transport: Debug + Send + Sync {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse>;
+}
+
+impl Transport for reqwest::blocking::Client {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let mut builder = self
+            .request(request.method, request.url)
+            .headers(request.headers);
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        if let Some(timeout) = request.timeout {
+            builder = builder.timeout(timeout);
+        }
+        let r = builder.send().chain_err(|| ErrorKind::Transport)?;
+        let status = r.status();
+        let headers = r.headers().clone();
+        let body = r.bytes().chain_err(|| ErrorKind::Transport)?;
+        Ok(HttpResponse {
+            status,
+            headers,
+            body: body.to_vec(),
+        })
+    }
+}
+
+/// Sends the requests of the async `Client`.
+#[cfg(feature = "async")]
+#[async_trait]
+pub trait AsyncTransport: Debug + Send + Sync {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse>;
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl AsyncTransport for reqwest::Client {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let mut builder = self
+            .request(request.method, request.url)
+            .headers(request.headers);
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        if let Some(timeout) = request.timeout {
+            builder = builder.timeout(timeout);
+        }
+        let r = builder.send().await.chain_err(|| ErrorKind::Transport)?;
+        let status = r.status();
+        let headers = r.headers().clone();
+        let body = r.bytes().await.chain_err(|| ErrorKind::Transport)?;
+        Ok(HttpResponse {
+            status,
+            headers,
+            body: body.to_vec(),
+        })
+    }
+}
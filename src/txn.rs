@@ -0,0 +1,299 @@
+//! Atomic transactions of the `/v1/txn` endpoint, over KV entries and the
+//! nodes, services and checks of the catalog.
+//!
+//! ```no_run
+//! use consul::txn::{Transaction, Txn};
+//! use consul::{Client, Config};
+//!
+//! let client = Client::new(Config::new().unwrap());
+//! let txn = Txn::new()
+//!     .kv_check_index("features/version", 42)
+//!     .kv_set("features/new-ui", b"on")
+//!     .kv_set("features/version", b"43");
+//! match client.txn(&txn, None) {
+//!     Ok((results, _)) => println!("{} results", results.len()),
+//!     Err(e) => eprintln!("transaction failed: {}", e),
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::agent::AgentService;
+use crate::errors::{Error, ErrorKind, Result, ResultExt};
+use crate::health::{HealthCheck, Node};
+use crate::kv::{base64_value, KVPair};
+use crate::request::put;
+use crate::{Client, WriteMeta, WriteOptions};
+
+/// Operations on a KV entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[allow(clippy::upper_case_acronyms)]
+pub enum KVVerb {
+    Set,
+    Cas,
+    Lock,
+    Unlock,
+    Get,
+    GetTree,
+    CheckIndex,
+    CheckSession,
+    CheckNotExists,
+    Delete,
+    DeleteTree,
+    DeleteCas,
+}
+
+/// Operations on a node, service or check.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CatalogVerb {
+    Set,
+    Cas,
+    Get,
+    Delete,
+    DeleteCas,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[allow(clippy::upper_case_acronyms)]
+pub struct KVTxnOp {
+    pub Verb: KVVerb,
+    pub Key: String,
+    #[serde(with = "base64_value")]
+    pub Value: Vec<u8>,
+    pub Flags: u64,
+    /// Expected `ModifyIndex` of the `cas`, `delete-cas` and `check-index`
+    /// operations.
+    pub Index: u64,
+    pub Session: String,
+    pub Namespace: Option<String>,
+    pub Partition: Option<String>,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct NodeTxnOp {
+    pub Verb: CatalogVerb,
+    pub Node: Node,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct ServiceTxnOp {
+    pub Verb: CatalogVerb,
+    /// Node of the service.
+    pub Node: String,
+    pub Service: AgentService,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct CheckTxnOp {
+    pub Verb: CatalogVerb,
+    pub Check: HealthCheck,
+}
+
+/// An operation of a transaction.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum TxnOp {
+    KV(KVTxnOp),
+    Node(NodeTxnOp),
+    Service(ServiceTxnOp),
+    Check(CheckTxnOp),
+}
+
+/// An entry read or written by a transaction.
+#[derive(Debug, Eq, PartialEq, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum TxnResult {
+    KV(KVPair),
+    Node(Node),
+    Service(AgentService),
+    Check(HealthCheck),
+}
+
+/// Why a transaction was rolled back, reported for the operation at
+/// `OpIndex`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TxnError {
+    pub OpIndex: usize,
+    pub What: String,
+}
+
+impl fmt::Display for TxnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "operation {}: {}", self.OpIndex, self.What)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct TxnResponse {
+    pub Results: Option<Vec<TxnResult>>,
+    pub Errors: Option<Vec<TxnError>>,
+}
+
+/// Builder of the operations of a transaction, applied in order and all
+/// together or not at all.
+#[derive(Debug, Default)]
+pub struct Txn {
+    pub ops: Vec<TxnOp>,
+}
+
+impl Txn {
+    pub fn new() -> Txn {
+        Txn::default()
+    }
+
+    pub fn op(mut self, op: TxnOp) -> Self {
+        self.ops.push(op);
+        self
+    }
+
+    fn kv(self, verb: KVVerb, key: &str, value: &[u8], index: u64, session: &str) -> Self {
+        self.op(TxnOp::KV(KVTxnOp {
+            Verb: verb,
+            Key: key.to_owned(),
+            Value: value.to_vec(),
+            Flags: 0,
+            Index: index,
+            Session: session.to_owned(),
+            Namespace: None,
+            Partition: None,
+        }))
+    }
+
+    pub fn kv_set(self, key: &str, value: &[u8]) -> Self {
+        self.kv(KVVerb::Set, key, value, 0, "")
+    }
+
+    /// Sets the pair if its key was not modified since its `ModifyIndex`, a
+    /// missing index meaning the key must not exist yet.
+    pub fn kv_cas(self, pair: &KVPair) -> Self {
+        let index = pair.ModifyIndex.unwrap_or(0);
+        let txn = self.kv(KVVerb::Cas, &pair.Key, &pair.Value, index, "");
+        txn.with_flags(pair.Flags.unwrap_or(0))
+    }
+
+    pub fn kv_lock(self, key: &str, value: &[u8], session: &str) -> Self {
+        self.kv(KVVerb::Lock, key, value, 0, session)
+    }
+
+    pub fn kv_unlock(self, key: &str, value: &[u8], session: &str) -> Self {
+        self.kv(KVVerb::Unlock, key, value, 0, session)
+    }
+
+    pub fn kv_get(self, key: &str) -> Self {
+        self.kv(KVVerb::Get, key, &[], 0, "")
+    }
+
+    pub fn kv_get_tree(self, prefix: &str) -> Self {
+        self.kv(KVVerb::GetTree, prefix, &[], 0, "")
+    }
+
+    /// Fails the transaction unless the `ModifyIndex` of the key is `index`.
+    pub fn kv_check_index(self, key: &str, index: u64) -> Self {
+        self.kv(KVVerb::CheckIndex, key, &[], index, "")
+    }
+
+    /// Fails the transaction unless the key is locked by the session.
+    pub fn kv_check_session(self, key: &str, session: &str) -> Self {
+        self.kv(KVVerb::CheckSession, key, &[], 0, session)
+    }
+
+    pub fn kv_check_not_exists(self, key: &str) -> Self {
+        self.kv(KVVerb::CheckNotExists, key, &[], 0, "")
+    }
+
+    pub fn kv_delete(self, key: &str) -> Self {
+        self.kv(KVVerb::Delete, key, &[], 0, "")
+    }
+
+    pub fn kv_delete_tree(self, prefix: &str) -> Self {
+        self.kv(KVVerb::DeleteTree, prefix, &[], 0, "")
+    }
+
+    pub fn kv_delete_cas(self, key: &str, index: u64) -> Self {
+        self.kv(KVVerb::DeleteCas, key, &[], index, "")
+    }
+
+    /// Sets the flags of the last KV operation.
+    pub fn with_flags(mut self, flags: u64) -> Self {
+        if let Some(TxnOp::KV(op)) = self.ops.last_mut() {
+            op.Flags = flags;
+        }
+        self
+    }
+
+    /// Sets the namespace and admin partition of the last KV operation,
+    /// overriding the ones of the request.
+    pub fn with_tenancy(mut self, namespace: Option<&str>, partition: Option<&str>) -> Self {
+        if let Some(TxnOp::KV(op)) = self.ops.last_mut() {
+            op.Namespace = namespace.map(String::from);
+            op.Partition = partition.map(String::from);
+        }
+        self
+    }
+
+    /// Operation on a node, its `ModifyIndex` being checked by `Cas` and
+    /// `DeleteCas`.
+    pub fn node(self, verb: CatalogVerb, node: Node) -> Self {
+        self.op(TxnOp::Node(NodeTxnOp {
+            Verb: verb,
+            Node: node,
+        }))
+    }
+
+    /// Operation on a service of a node, its `ModifyIndex` being checked by
+    /// `Cas` and `DeleteCas`.
+    pub fn service(self, verb: CatalogVerb, node: &str, service: AgentService) -> Self {
+        self.op(TxnOp::Service(ServiceTxnOp {
+            Verb: verb,
+            Node: node.to_owned(),
+            Service: service,
+        }))
+    }
+
+    /// Operation on a check, identified by its `Node` and `CheckID`, its
+    /// `ModifyIndex` being checked by `Cas` and `DeleteCas`.
+    pub fn check(self, verb: CatalogVerb, check: HealthCheck) -> Self {
+        self.op(TxnOp::Check(CheckTxnOp {
+            Verb: verb,
+            Check: check,
+        }))
+    }
+}
+
+pub trait Transaction {
+    /// Applies the operations of the transaction atomically, returning the
+    /// entries they read, wrote or checked in order. Every operation yields
+    /// its entry, `get-tree` all the entries under its prefix, except the
+    /// deletes and `check-not-exists`, which are skipped in the results.
+    /// When Consul rolls the transaction back, it fails with
+    /// `ErrorKind::TxnRolledBack` listing the failed operations.
+    fn txn(&self, txn: &Txn, o: Option<&WriteOptions>) -> Result<(Vec<TxnResult>, WriteMeta)>;
+}
+
+impl Transaction for Client {
+    fn txn(&self, txn: &Txn, o: Option<&WriteOptions>) -> Result<(Vec<TxnResult>, WriteMeta)> {
+        let result = put("/v1/txn", Some(&txn.ops), &self.config, HashMap::new(), o);
+        txn_result(result)
+    }
+}
+
+/// Turns the response of a transaction into its results, or into
+/// `ErrorKind::TxnRolledBack` for its 409 Conflict status.
+pub(crate) fn txn_result(
+    result: Result<(TxnResponse, WriteMeta)>,
+) -> Result<(Vec<TxnResult>, WriteMeta)> {
+    match result {
+        Ok((response, meta)) => Ok((response.Results.unwrap_or_default(), meta)),
+        Err(Error(ErrorKind::ConsulError(409, body), _)) => {
+            let response: TxnResponse =
+                serde_json::from_str(&body).chain_err(|| ErrorKind::Decode)?;
+            Err(ErrorKind::TxnRolledBack(response.Errors.unwrap_or_default()).into())
+        }
+        Err(e) => Err(e),
+    }
+}
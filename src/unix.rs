@@ -0,0 +1,170 @@
+//! Minimal HTTP/1.1 client used to talk to an agent listening on a Unix domain
+//! socket, as reqwest only speaks TCP.
+
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::str;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+
+use crate::errors::{Result, ResultExt};
+use crate::transport::{HttpRequest, HttpResponse};
+
+const UNIX_SCHEME: &str = "unix://";
+/// Timeout of the requests without one, as the blocking reqwest client.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns the socket path of a `unix://` agent address.
+pub(crate) fn socket_path(address: &str) -> Option<&str> {
+    address.strip_prefix(UNIX_SCHEME)
+}
+
+/// Serializes a request, asking the agent to close the connection once the
+/// response is sent so it can be read up to EOF.
+fn encode_request(request: &HttpRequest) -> Vec<u8> {
+    let url = &request.url;
+    let mut target = url.path().to_owned();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let body = request.body.as_deref().unwrap_or_default();
+    let mut raw = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\n",
+        request.method, target
+    )
+    .into_bytes();
+    for (name, value) in &request.headers {
+        raw.extend_from_slice(name.as_str().as_bytes());
+        raw.extend_from_slice(b": ");
+        raw.extend_from_slice(value.as_bytes());
+        raw.extend_from_slice(b"\r\n");
+    }
+    raw.extend_from_slice(
+        format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .as_bytes(),
+    );
+    raw.extend_from_slice(body);
+    raw
+}
+
+/// Parses a complete response read up to EOF.
+fn decode_response(raw: &[u8]) -> Result<HttpResponse> {
+    let head_end = find(raw, b"\r\n\r\n").ok_or("Truncated HTTP response")?;
+    let head = str::from_utf8(&raw[..head_end]).chain_err(|| "Invalid HTTP response headers")?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .ok_or("Invalid HTTP status line")?;
+    let status = StatusCode::from_bytes(status.as_bytes()).chain_err(|| "Invalid HTTP status")?;
+
+    let mut headers = HeaderMap::new();
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or("Invalid HTTP header")?;
+        headers.append(
+            HeaderName::from_bytes(name.trim().as_bytes()).chain_err(|| "Invalid HTTP header")?,
+            HeaderValue::from_bytes(value.trim().as_bytes()).chain_err(|| "Invalid HTTP header")?,
+        );
+    }
+
+    let body = &raw[head_end + 4..];
+    let chunked = headers
+        .get("Transfer-Encoding")
+        .map(|v| v.as_bytes().eq_ignore_ascii_case(b"chunked"))
+        .unwrap_or(false);
+    let body = if chunked {
+        decode_chunked(body)?
+    } else {
+        match headers.get("Content-Length") {
+            Some(len) => {
+                let len: usize = len
+                    .to_str()
+                    .ok()
+                    .and_then(|l| l.parse().ok())
+                    .ok_or("Invalid Content-Length")?;
+                body.get(..len).ok_or("Truncated HTTP body")?.to_vec()
+            }
+            None => body.to_vec(),
+        }
+    };
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn decode_chunked(mut raw: &[u8]) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = find(raw, b"\r\n").ok_or("Truncated chunked body")?;
+        let size = str::from_utf8(&raw[..line_end])
+            .ok()
+            .and_then(|l| usize::from_str_radix(l.split(';').next()?.trim(), 16).ok())
+            .ok_or("Invalid chunk size")?;
+        raw = &raw[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        body.extend_from_slice(raw.get(..size).ok_or("Truncated chunked body")?);
+        raw = raw.get(size + 2..).ok_or("Truncated chunked body")?;
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+pub(crate) fn send(path: &str, request: &HttpRequest) -> Result<HttpResponse> {
+    let mut stream = UnixStream::connect(path)
+        .chain_err(|| format!("Failed to connect to unix socket '{}'", path))?;
+    let timeout = request.timeout.unwrap_or(DEFAULT_TIMEOUT);
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .chain_err(|| "Failed to set the unix socket timeout")?;
+    stream
+        .write_all(&encode_request(request))
+        .chain_err(|| "Failed to send request over unix socket")?;
+    let mut raw = Vec::new();
+    stream
+        .read_to_end(&mut raw)
+        .chain_err(|| "Failed to read response over unix socket")?;
+    decode_response(&raw)
+}
+
+#[cfg(feature = "async")]
+async fn exchange_async(path: &str, request: &HttpRequest) -> Result<Vec<u8>> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::net::UnixStream::connect(path)
+        .await
+        .chain_err(|| format!("Failed to connect to unix socket '{}'", path))?;
+    stream
+        .write_all(&encode_request(request))
+        .await
+        .chain_err(|| "Failed to send request over unix socket")?;
+    let mut raw = Vec::new();
+    stream
+        .read_to_end(&mut raw)
+        .await
+        .chain_err(|| "Failed to read response over unix socket")?;
+    Ok(raw)
+}
+
+#[cfg(feature = "async")]
+pub(crate) async fn send_async(path: &str, request: &HttpRequest) -> Result<HttpResponse> {
+    let raw = match request.timeout {
+        Some(timeout) => tokio::time::timeout(timeout, exchange_async(path, request))
+            .await
+            .chain_err(|| "Timed out waiting for the response over unix socket")??,
+        None => exchange_async(path, request).await?,
+    };
+    decode_response(&raw)
+}
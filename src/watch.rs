@@ -0,0 +1,199 @@
+//! Blocking query watcher, turning any query into an iterator over its
+//! changes.
+//!
+//! ```no_run
+//! use consul::health::Health;
+//! use consul::watch::{watch, WatchOptions};
+//! use consul::{Client, Config};
+//!
+//! let client = Client::new(Config::new().unwrap());
+//! let services = watch(
+//!     move |q| client.service("web", None, true, Some(q)),
+//!     WatchOptions::default(),
+//! );
+//! for result in services {
+//!     match result {
+//!         Ok((entries, _)) => println!("{} healthy instances", entries.len()),
+//!         Err(e) => eprintln!("watch failed: {}", e),
+//!     }
+//! }
+//! ```
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::errors::Result;
+use crate::{QueryMeta, QueryOptions};
+
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    /// Base options of the queries, their `wait_index` and `wait_time` being
+    /// managed by the watcher.
+    pub query: QueryOptions,
+    /// Maximum duration of each blocking query.
+    pub wait_time: Duration,
+    /// Maximum duration of each blocking query while a `StopHandle` of the
+    /// watch exists, bounding how long `StopHandle::stop` takes to end it.
+    pub stop_wait_time: Duration,
+    /// Minimum delay between two queries returning the same index, protecting
+    /// the servers from endpoints returning immediately.
+    pub min_interval: Duration,
+    /// Delay before retrying a failed query, doubled on each consecutive error.
+    pub initial_error_backoff: Duration,
+    pub max_error_backoff: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            query: QueryOptions::default(),
+            wait_time: Duration::from_secs(300),
+            stop_wait_time: Duration::from_secs(10),
+            min_interval: Duration::from_secs(1),
+            initial_error_backoff: Duration::from_secs(1),
+            max_error_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Stops a `Watch`, possibly from another thread. The watch ends right away
+/// when it is waiting between queries, else once its current query returns,
+/// which takes up to `WatchOptions::stop_wait_time` as the queries in flight
+/// cannot be interrupted.
+#[derive(Clone, Debug, Default)]
+pub struct StopHandle {
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl StopHandle {
+    pub fn stop(&self) {
+        let (stopped, condvar) = &*self.stopped;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_all();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.stopped.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether another handle may stop the watch.
+    fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.stopped) > 1
+    }
+
+    /// Sleeps for the given duration, returning `true` if stopped meanwhile.
+    pub(crate) fn sleep(&self, duration: Duration) -> bool {
+        let (stopped, condvar) = &*self.stopped;
+        let guard = stopped.lock().unwrap_or_else(|e| e.into_inner());
+        let (guard, _) = condvar
+            .wait_timeout_while(guard, duration, |stopped| !*stopped)
+            .unwrap_or_else(|e| e.into_inner());
+        *guard
+    }
+}
+
+/// Iterator over the changes of a blocking query, see `watch`.
+pub struct Watch<T, F>
+where
+    F: FnMut(&QueryOptions) -> Result<(T, QueryMeta)>,
+{
+    query: F,
+    options: WatchOptions,
+    index: u64,
+    last_yielded: Option<u64>,
+    errors: u32,
+    delay: Option<Duration>,
+    stop: StopHandle,
+}
+
+/// Watches the results of `query`, called with the `QueryOptions` to use.
+///
+/// The iterator yields the first result, then a result each time the index
+/// returned by Consul changes, and the errors of the failed queries. It follows
+/// the Consul rules for blocking queries: the index is reset when it goes
+/// backwards, queries returning the same index are rate limited, and failed
+/// queries are retried with an exponential backoff.
+pub fn watch<T, F>(query: F, options: WatchOptions) -> Watch<T, F>
+where
+    F: FnMut(&QueryOptions) -> Result<(T, QueryMeta)>,
+{
+    Watch {
+        query,
+        options,
+        index: 0,
+        last_yielded: None,
+        errors: 0,
+        delay: None,
+        stop: StopHandle::default(),
+    }
+}
+
+impl<T, F> Watch<T, F>
+where
+    F: FnMut(&QueryOptions) -> Result<(T, QueryMeta)>,
+{
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    fn error_backoff(&self) -> Duration {
+        self.options
+            .initial_error_backoff
+            .checked_mul(1 << self.errors.saturating_sub(1).min(31))
+            .unwrap_or(self.options.max_error_backoff)
+            .min(self.options.max_error_backoff)
+    }
+}
+
+impl<T, F> Iterator for Watch<T, F>
+where
+    F: FnMut(&QueryOptions) -> Result<(T, QueryMeta)>,
+{
+    type Item = Result<(T, QueryMeta)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(delay) = self.delay.take() {
+                if self.stop.sleep(delay) {
+                    return None;
+                }
+            }
+            if self.stop.is_stopped() {
+                return None;
+            }
+
+            let mut options = self.options.query.clone();
+            options.wait_index = Some(self.index).filter(|i| *i > 0);
+            options.wait_time = Some(if self.stop.is_shared() {
+                self.options.wait_time.min(self.options.stop_wait_time)
+            } else {
+                self.options.wait_time
+            });
+            let started = Instant::now();
+            let (value, meta) = match (self.query)(&options) {
+                Ok(result) => result,
+                Err(e) => {
+                    self.errors += 1;
+                    self.delay = Some(self.error_backoff());
+                    return Some(Err(e));
+                }
+            };
+            self.errors = 0;
+
+            let new_index = meta.last_index.unwrap_or(0);
+            self.index = if new_index < self.index {
+                // The index went backwards, e.g. after a snapshot restore
+                0
+            } else {
+                // An index of 0 would make the next query return immediately
+                new_index.max(1)
+            };
+            if self.last_yielded == Some(new_index) {
+                self.delay = self.options.min_interval.checked_sub(started.elapsed());
+                continue;
+            }
+            self.last_yielded = Some(new_index);
+            return Some(Ok((value, meta)));
+        }
+    }
+}
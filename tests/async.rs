@@ -0,0 +1,163 @@
+#![cfg(feature = "async")]
+extern crate consul;
+use consul::asynchronous::Client;
+use consul::errors::ErrorKind;
+use consul::kv::{DeleteOptions, KVPair};
+use consul::test_support::FakeConsul;
+
+#[tokio::test]
+async fn async_ds_test() {
+    use consul::asynchronous::catalog::Catalog;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let r = client.datacenters().await.unwrap();
+    assert_eq!(r.0, ["dc1"]);
+}
+
+#[tokio::test]
+async fn async_kv_test() {
+    use consul::asynchronous::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+
+    let pair = KVPair {
+        Key: String::from("asynctestkey"),
+        Value: b"testvalue".to_vec(),
+        ..Default::default()
+    };
+
+    assert!(client.put(&pair, None).await.unwrap().0);
+    assert!(client.get("asynctestkey", None).await.unwrap().0.is_some());
+
+    assert!(client.delete("asynctestkey", None).await.unwrap().0);
+}
+
+#[tokio::test]
+async fn async_kv_cas_test() {
+    use consul::asynchronous::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+
+    let pair = KVPair {
+        Key: String::from("config"),
+        Value: b"v1".to_vec(),
+        ..Default::default()
+    };
+    client.put_cas(&pair, None).await.unwrap();
+    let err = client.put_cas(&pair, None).await.unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::CasLost(_)));
+
+    let (value, _) = client
+        .update(
+            "config",
+            3,
+            |pair| Ok([&pair.unwrap().Value[..], b"+"].concat()),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(value, b"v1+");
+
+    let current = client.get("config", None).await.unwrap().0.unwrap();
+    let err = client.delete_cas(&pair, None).await.unwrap_err();
+    assert!(err.to_string().contains("no ModifyIndex"));
+    let stale = KVPair {
+        ModifyIndex: Some(1),
+        ..current.clone()
+    };
+    let err = client.delete_cas(&stale, None).await.unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::CasLost(_)));
+    client.delete_cas(&current, None).await.unwrap();
+}
+
+#[tokio::test]
+async fn async_txn_test() {
+    use consul::asynchronous::txn::Transaction;
+    use consul::txn::Txn;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+
+    let txn = Txn::new().kv_set("a", b"1").kv_set("b", b"2");
+    let (results, _) = client.txn(&txn, None).await.unwrap();
+    assert_eq!(results.len(), 2);
+    let txn = Txn::new().kv_check_not_exists("a").kv_delete("b");
+    let err = client.txn(&txn, None).await.unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::TxnRolledBack(errors) if errors[0].OpIndex == 0));
+}
+
+#[tokio::test]
+async fn async_kv_typed_test() {
+    use consul::asynchronous::kv::KV;
+    use consul::kv::Codec;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+
+    let tags = vec![String::from("blue"), String::from("canary")];
+    client
+        .put_typed("web/tags", &tags, Codec::Json, None, None)
+        .await
+        .unwrap();
+    let (typed, _) = client
+        .get_typed::<Vec<String>>("web/tags", Codec::Json, None)
+        .await
+        .unwrap();
+    let typed = typed.unwrap();
+    assert_eq!(typed.value, tags);
+    client
+        .put_typed(
+            "web/tags",
+            &tags[..1],
+            Codec::Json,
+            typed.pair.ModifyIndex,
+            None,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn async_kv_trait_object_test() {
+    use consul::asynchronous::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let kv: Box<dyn KV + Send + Sync> = Box::new(Client::new(consul.config().unwrap()));
+
+    let pair = KVPair {
+        Key: String::from("mocked"),
+        Value: b"value".to_vec(),
+        ..Default::default()
+    };
+    assert!(kv.put(&pair, None).await.unwrap().0);
+    let pair = kv.get("mocked", None).await.unwrap().0.unwrap();
+    assert_eq!(pair.Value, b"value");
+}
+
+#[tokio::test]
+async fn async_kv_delete_with_test() {
+    use consul::asynchronous::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    for key in ["svc", "svc/a"].iter() {
+        let pair = KVPair {
+            Key: String::from(*key),
+            ..Default::default()
+        };
+        client.put(&pair, None).await.unwrap();
+    }
+
+    let pair = client.get("svc", None).await.unwrap().0.unwrap();
+    let stale = DeleteOptions {
+        cas: Some(pair.ModifyIndex.unwrap() - 1),
+        ..Default::default()
+    };
+    let err = client.delete_with("svc", &stale, None).await.unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::CasLost(_)));
+    let single = DeleteOptions::default();
+    assert!(client.delete_with("svc", &single, None).await.unwrap().0);
+    assert!(!client.delete_with("svc", &single, None).await.unwrap().0);
+    let recurse = DeleteOptions {
+        recurse: true,
+        ..Default::default()
+    };
+    assert!(client.delete_with("svc/", &recurse, None).await.unwrap().0);
+    assert!(!client.delete_with("svc/", &recurse, None).await.unwrap().0);
+}
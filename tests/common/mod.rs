@@ -0,0 +1,98 @@
+//! Canned HTTP responses served to the client, to test requests offline.
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+
+use consul::errors::Result;
+use consul::transport::{HttpRequest, HttpResponse, Transport};
+
+/// Reads a request and writes the response, returning the request head.
+fn exchange<S: Read + Write>(stream: S, response: &str) -> String {
+    let mut reader = BufReader::new(stream);
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" || line.is_empty() {
+            break;
+        }
+        head.push_str(&line);
+    }
+    reader.get_mut().write_all(response.as_bytes()).unwrap();
+    head
+}
+
+/// Serves a single response over TCP, returning the agent address and a handle
+/// yielding the request head.
+pub fn serve_once(response: &'static str) -> (String, thread::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        exchange(stream, response)
+    });
+    (address, handle)
+}
+
+/// Serves a single response over a Unix domain socket, returning the agent
+/// address and a handle yielding the request head.
+#[cfg(unix)]
+pub fn serve_unix_once(response: &'static str) -> (String, thread::JoinHandle<String>) {
+    let suffix: u64 = rand::thread_rng().gen();
+    let path = std::env::temp_dir().join(format!("consul-rust-{}.sock", suffix));
+    let listener = UnixListener::bind(&path).unwrap();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        exchange(stream, response)
+    });
+    (format!("unix://{}", path.display()), handle)
+}
+
+/// Replies with canned responses, recording the requests it is sent.
+#[derive(Debug, Default)]
+pub struct ScriptedTransport {
+    responses: Mutex<VecDeque<(u16, &'static str)>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl ScriptedTransport {
+    pub fn new(responses: &[(u16, &'static str)]) -> Arc<ScriptedTransport> {
+        Arc::new(ScriptedTransport {
+            responses: Mutex::new(responses.iter().cloned().collect()),
+            requests: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Transport for ScriptedTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        self.requests.lock().unwrap().push(request);
+        let (status, body) = self
+            .responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("unexpected request");
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Consul-Index", "42".parse().unwrap());
+        Ok(HttpResponse {
+            status: StatusCode::from_u16(status).unwrap(),
+            headers,
+            body: body.as_bytes().to_vec(),
+        })
+    }
+}
@@ -0,0 +1,69 @@
+extern crate consul;
+mod common;
+
+use std::time::Duration;
+
+use common::serve_once;
+use consul::{Client, Config, QueryOptions};
+
+#[test]
+fn consistency_stale_query_meta_test() {
+    use consul::catalog::Catalog;
+    let (address, server) = serve_once(
+        "HTTP/1.1 200 OK\r\nX-Consul-Index: 7\r\nX-Consul-KnownLeader: true\r\n\
+         X-Consul-LastContact: 250\r\nX-Consul-Effective-Consistency: stale\r\n\
+         Content-Length: 2\r\nConnection: close\r\n\r\n[]",
+    );
+    let mut config = Config::new().unwrap();
+    config.address = address;
+    let client = Client::new(config);
+
+    let options = QueryOptions {
+        allow_stale: true,
+        ..Default::default()
+    };
+    let (nodes, meta) = client.nodes(Some(&options)).unwrap();
+    assert!(nodes.is_empty());
+    assert!(meta.known_leader);
+    assert_eq!(meta.last_contact, Duration::from_millis(250));
+    assert_eq!(meta.effective_consistency.as_deref(), Some("stale"));
+    assert!(!meta.cache_hit);
+    assert!(server.join().unwrap().contains("stale="));
+}
+
+#[test]
+fn consistency_cached_query_test() {
+    use consul::catalog::Catalog;
+    let (address, server) = serve_once(
+        "HTTP/1.1 200 OK\r\nX-Cache: HIT\r\nAge: 12\r\n\
+         Content-Length: 2\r\nConnection: close\r\n\r\n{}",
+    );
+    let mut config = Config::new().unwrap();
+    config.address = address;
+    let client = Client::new(config);
+
+    let options = QueryOptions {
+        use_cache: true,
+        max_age: Some(Duration::from_secs(30)),
+        stale_if_error: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+    let (_, meta) = client.services(Some(&options)).unwrap();
+    assert!(meta.cache_hit);
+    assert_eq!(meta.cache_age, Some(Duration::from_secs(12)));
+    let request = server.join().unwrap().to_lowercase();
+    assert!(request.contains("cached="));
+    assert!(request.contains("cache-control: max-age=30, stale-if-error=60"));
+}
+
+#[test]
+fn consistency_conflicting_modes_test() {
+    use consul::catalog::Catalog;
+    let client = Client::new(Config::new().unwrap());
+    let options = QueryOptions {
+        allow_stale: true,
+        require_consistent: true,
+        ..Default::default()
+    };
+    assert!(client.nodes(Some(&options)).is_err());
+}
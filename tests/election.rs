@@ -0,0 +1,159 @@
+extern crate consul;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use consul::election::{ElectionOptions, LeaderElection};
+use consul::kv::{KVPair, KV};
+use consul::session::Session;
+use consul::test_support::FakeConsul;
+use consul::Client;
+
+struct Candidate {
+    election: LeaderElection,
+    elected: Arc<AtomicUsize>,
+    deposed: Arc<AtomicUsize>,
+}
+
+fn candidate(consul: &FakeConsul, value: &[u8]) -> Candidate {
+    let client = Client::new(consul.config().unwrap());
+    let options = ElectionOptions {
+        value: value.to_vec(),
+        retry_time: Duration::from_millis(100),
+        ..ElectionOptions::new("jobs/leader")
+    };
+    let (elected, deposed) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let election = {
+        let (elected, deposed) = (elected.clone(), deposed.clone());
+        LeaderElection::new(client, options)
+            .on_elected(move || {
+                elected.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_deposed(move || {
+                deposed.fetch_add(1, Ordering::SeqCst);
+            })
+    };
+    Candidate {
+        election,
+        elected,
+        deposed,
+    }
+}
+
+fn eventually<F: Fn() -> bool>(condition: F) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+#[test]
+fn election_test() {
+    let consul = FakeConsul::start().unwrap();
+    let (mut first, mut second) = (candidate(&consul, b"one"), candidate(&consul, b"two"));
+    assert_eq!(first.election.leader().unwrap(), None);
+
+    first.election.campaign().unwrap();
+    assert!(eventually(|| first.election.is_leader()));
+    assert_eq!(first.elected.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        first.election.leader().unwrap().as_deref(),
+        Some(&b"one"[..])
+    );
+
+    second.election.campaign().unwrap();
+    thread::sleep(Duration::from_millis(300));
+    assert!(!second.election.is_leader());
+    assert_eq!(
+        second.election.leader().unwrap().as_deref(),
+        Some(&b"one"[..])
+    );
+
+    first.election.resign().unwrap();
+    assert!(!first.election.is_leader());
+    assert_eq!(first.deposed.load(Ordering::SeqCst), 1);
+    assert!(eventually(|| second.election.is_leader()));
+    assert_eq!(
+        second.election.leader().unwrap().as_deref(),
+        Some(&b"two"[..])
+    );
+    assert_eq!(first.elected.load(Ordering::SeqCst), 1);
+
+    // Resigning destroys the session of the campaign
+    drop(second);
+    let client = Client::new(consul.config().unwrap());
+    assert_eq!(first.election.leader().unwrap(), None);
+    assert!(Session::list(&client, None).unwrap().0.is_empty());
+}
+
+#[test]
+fn election_recover_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let mut candidate = candidate(&consul, b"one");
+
+    candidate.election.campaign().unwrap();
+    assert!(eventually(|| candidate.election.is_leader()));
+    let pair = client.get("jobs/leader", None).unwrap().0.unwrap();
+    let session = pair.Session.unwrap();
+
+    client.destroy(&session, None).unwrap();
+    assert!(eventually(|| candidate.deposed.load(Ordering::SeqCst) == 1));
+    assert!(eventually(|| candidate.elected.load(Ordering::SeqCst) == 2));
+    assert!(candidate.election.is_leader());
+    let pair = client.get("jobs/leader", None).unwrap().0.unwrap();
+    assert_ne!(pair.Session.unwrap(), session);
+}
+
+#[test]
+fn election_conflict_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let pair = KVPair {
+        Key: String::from("jobs/leader"),
+        Value: b"config".to_vec(),
+        ..Default::default()
+    };
+    client.put(&pair, None).unwrap();
+
+    let mut candidate = candidate(&consul, b"one");
+    let err = candidate.election.campaign().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The key 'jobs/leader' is not used as a lock"
+    );
+    assert!(!candidate.election.is_leader());
+}
+
+#[test]
+fn election_resign_from_callback_test() {
+    use std::sync::Mutex;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let slot: Arc<Mutex<Option<LeaderElection>>> = Arc::new(Mutex::new(None));
+    let deposed = Arc::new(AtomicUsize::new(0));
+    let election = {
+        let (slot, deposed) = (slot.clone(), deposed.clone());
+        LeaderElection::new(client.clone(), ElectionOptions::new("jobs/leader"))
+            .on_elected(move || {
+                // Dropping the election resigns it
+                slot.lock().unwrap().take();
+            })
+            .on_deposed(move || {
+                deposed.fetch_add(1, Ordering::SeqCst);
+            })
+    };
+    let mut slot_guard = slot.lock().unwrap();
+    slot_guard.insert(election).campaign().unwrap();
+    drop(slot_guard);
+
+    assert!(eventually(|| slot.lock().unwrap().is_none()));
+    assert!(eventually(|| deposed.load(Ordering::SeqCst) == 1));
+    let pair = client.get("jobs/leader", None).unwrap().0.unwrap();
+    assert_eq!(pair.Session, None);
+}
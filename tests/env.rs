@@ -0,0 +1,100 @@
+extern crate consul;
+mod common;
+
+use std::env;
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use common::ScriptedTransport;
+use consul::kv::KV;
+use consul::{Client, Config, HttpBasicAuth, TokenFile};
+
+// All the environment variables are checked from a single test, as they are
+// shared by the tests running in parallel.
+#[test]
+fn env_config_test() {
+    let token_path = env::temp_dir().join("consul-rust-env-test-token");
+    fs::write(&token_path, "file-token\n").unwrap();
+
+    env::set_var("CONSUL_HTTP_ADDR", "consul.example.com:8501");
+    env::set_var("CONSUL_HTTP_SSL", "true");
+    env::set_var("CONSUL_HTTP_AUTH", "operator:secret");
+    env::set_var("CONSUL_HTTP_TOKEN_FILE", &token_path);
+    env::set_var("CONSUL_DATACENTER", "dc2");
+    env::set_var("CONSUL_NAMESPACE", "team-a");
+    env::set_var("CONSUL_PARTITION", "part-1");
+
+    let config = Config::new_from_env().unwrap();
+    assert_eq!(config.address, "https://consul.example.com:8501");
+    assert_eq!(
+        config.http_auth,
+        Some(HttpBasicAuth {
+            username: String::from("operator"),
+            password: Some(String::from("secret")),
+        })
+    );
+    assert_eq!(config.token, None);
+    assert_eq!(config.token_file.unwrap().token().unwrap(), "file-token");
+    assert_eq!(config.datacenter.as_deref(), Some("dc2"));
+    assert_eq!(config.namespace.as_deref(), Some("team-a"));
+    assert_eq!(config.partition.as_deref(), Some("part-1"));
+
+    // The token file overrides the token, unless empty
+    env::set_var("CONSUL_HTTP_TOKEN", "stale-token");
+    let mut config = Config::new_from_env().unwrap();
+    assert_eq!(config.token.as_deref(), Some("stale-token"));
+    let transport = ScriptedTransport::new(&[(200, "[]"), (200, "[]")]);
+    config.transport = Some(transport.clone());
+    let client = Client::new(config);
+    client.list("foo", None).unwrap();
+    fs::write(&token_path, "").unwrap();
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .open(&token_path)
+        .unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(10))
+        .unwrap();
+    client.list("foo", None).unwrap();
+    let requests = transport.requests();
+    assert_eq!(requests[0].headers["X-Consul-Token"], "file-token");
+    assert_eq!(requests[1].headers["X-Consul-Token"], "stale-token");
+
+    env::set_var("CONSUL_HTTP_SSL", "maybe");
+    let err = Config::new_from_env().unwrap_err();
+    assert!(err.to_string().contains("CONSUL_HTTP_SSL"));
+    env::remove_var("CONSUL_HTTP_SSL");
+
+    env::set_var("CONSUL_HTTP_AUTH", ":secret");
+    assert!(Config::new_from_env().is_err());
+
+    for name in [
+        "CONSUL_HTTP_ADDR",
+        "CONSUL_HTTP_AUTH",
+        "CONSUL_HTTP_TOKEN",
+        "CONSUL_HTTP_TOKEN_FILE",
+        "CONSUL_DATACENTER",
+        "CONSUL_NAMESPACE",
+        "CONSUL_PARTITION",
+    ]
+    .iter()
+    {
+        env::remove_var(name);
+    }
+    fs::remove_file(&token_path).unwrap();
+}
+
+#[test]
+fn token_file_rotation_test() {
+    let path = env::temp_dir().join("consul-rust-rotated-token");
+    fs::write(&path, "first").unwrap();
+    let token_file = TokenFile::new(&path);
+    assert_eq!(token_file.token().unwrap(), "first");
+
+    fs::write(&path, "second").unwrap();
+    // Make sure the modification time changes on coarse grained filesystems
+    let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(token_file.token().unwrap(), "second");
+    fs::remove_file(&path).unwrap();
+}
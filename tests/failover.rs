@@ -0,0 +1,137 @@
+extern crate consul;
+use std::collections::HashSet;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+
+use consul::catalog::Catalog;
+use consul::errors::{Error, ErrorKind, Result};
+use consul::kv::KV;
+use consul::test_support::FakeConsul;
+use consul::transport::{HttpRequest, HttpResponse, Transport};
+use consul::{Client, Config, FailoverPolicy, RetryPolicy};
+
+/// Answers for the agents which are up, recording the agent of each request.
+#[derive(Debug, Default)]
+struct Agents {
+    down: Mutex<HashSet<String>>,
+    served: Mutex<Vec<String>>,
+}
+
+impl Agents {
+    fn set_down(&self, host: &str, down: bool) {
+        let mut hosts = self.down.lock().unwrap();
+        if down {
+            hosts.insert(host.to_owned());
+        } else {
+            hosts.remove(host);
+        }
+    }
+
+    fn last_served(&self) -> String {
+        self.served.lock().unwrap().last().unwrap().clone()
+    }
+}
+
+impl Transport for Agents {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let host = request.url.host_str().unwrap().to_owned();
+        if self.down.lock().unwrap().contains(&host) {
+            let refused = io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused");
+            return Err(Error::with_chain(refused, ErrorKind::Transport));
+        }
+        self.served.lock().unwrap().push(host);
+        Ok(HttpResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: b"[\"dc1\"]".to_vec(),
+        })
+    }
+}
+
+fn client(agents: &Arc<Agents>, reprobe_interval: Duration) -> Client {
+    let mut config = Config::new().unwrap();
+    config.address = String::from("http://agent-a:8500");
+    config.fallback_addresses = vec![
+        String::from("http://agent-b:8500"),
+        String::from("http://agent-c:8500"),
+    ];
+    config.failover = FailoverPolicy { reprobe_interval };
+    config.retry = RetryPolicy::none();
+    config.transport = Some(agents.clone());
+    Client::new(config)
+}
+
+#[test]
+fn failover_sticky_test() {
+    let agents = Arc::new(Agents::default());
+    let client = client(&agents, Duration::from_secs(3600));
+
+    let (_, meta) = client.datacenters().unwrap();
+    assert_eq!(meta.address, "http://agent-a:8500");
+
+    agents.set_down("agent-a", true);
+    agents.set_down("agent-b", true);
+    let (_, meta) = client.datacenters().unwrap();
+    assert_eq!(meta.address, "http://agent-c:8500");
+    assert_eq!(agents.last_served(), "agent-c");
+
+    // The requests stick to the agent which answered
+    agents.set_down("agent-a", false);
+    agents.set_down("agent-b", false);
+    let (_, meta) = client.datacenters().unwrap();
+    assert_eq!(meta.address, "http://agent-c:8500");
+
+    agents.set_down("agent-c", true);
+    let (_, meta) = client.datacenters().unwrap();
+    assert_eq!(meta.address, "http://agent-a:8500");
+}
+
+#[test]
+fn failover_reprobe_test() {
+    let agents = Arc::new(Agents::default());
+    let client = client(&agents, Duration::from_millis(100));
+
+    agents.set_down("agent-a", true);
+    let (_, meta) = client.datacenters().unwrap();
+    assert_eq!(meta.address, "http://agent-b:8500");
+
+    agents.set_down("agent-a", false);
+    let (_, meta) = client.datacenters().unwrap();
+    assert_eq!(meta.address, "http://agent-b:8500");
+
+    thread::sleep(Duration::from_millis(150));
+    let (_, meta) = client.datacenters().unwrap();
+    assert_eq!(meta.address, "http://agent-a:8500");
+}
+
+#[test]
+fn failover_all_down_test() {
+    let agents = Arc::new(Agents::default());
+    let client = client(&agents, Duration::from_secs(3600));
+    for agent in ["agent-a", "agent-b", "agent-c"].iter() {
+        agents.set_down(agent, true);
+    }
+    let err = client.datacenters().unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Transport));
+}
+
+#[test]
+fn failover_connection_refused_test() {
+    let consul = FakeConsul::start().unwrap();
+    let mut config = Config::new().unwrap();
+    config.address = String::from("http://127.0.0.1:1");
+    config.fallback_addresses = vec![consul.address().to_owned()];
+    config.retry = RetryPolicy::none();
+    let client = Client::new(config);
+
+    let (pairs, meta) = client.list("", None).unwrap();
+    assert!(pairs.is_empty());
+    assert_eq!(meta.address, consul.address());
+    let (_, meta) = client.delete("foo", None).unwrap();
+    assert_eq!(meta.address, consul.address());
+}
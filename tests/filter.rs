@@ -0,0 +1,99 @@
+extern crate consul;
+mod common;
+
+use common::{serve_once, ScriptedTransport};
+use consul::filter::Selector;
+use consul::{Client, Config, QueryOptions};
+
+#[test]
+fn filter_quoting_test() {
+    let filter = Selector::eq("Service.Meta.note", "say \"hi\"\\\n")
+        .or(Selector::is_not_empty("Checks"))
+        .not();
+    assert_eq!(
+        filter.to_expression().unwrap(),
+        r#"not (Service.Meta.note == "say \"hi\"\\\n" or Checks is not empty)"#
+    );
+}
+
+#[test]
+fn filter_precedence_test() {
+    let filter = Selector::eq("A", "1")
+        .or(Selector::eq("B", "2"))
+        .and(Selector::ne("C", "3").and(Selector::matches("D", "^x.*")));
+    assert_eq!(
+        filter.to_expression().unwrap(),
+        r#"(A == "1" or B == "2") and C != "3" and D matches "^x.*""#
+    );
+}
+
+#[test]
+fn filter_index_selector_test() {
+    let key = Selector::index("Service.Meta", "my-key \"1\"");
+    assert_eq!(key, r#"Service.Meta["my-key \"1\""]"#);
+    assert_eq!(
+        Selector::eq(&key, "x").to_expression().unwrap(),
+        r#"Service.Meta["my-key \"1\""] == "x""#
+    );
+    for selector in [
+        "Service.Meta[`my-key`]",
+        r#"Meta["a"].Value"#,
+        "Service/Name.v2_x",
+    ]
+    .iter()
+    {
+        assert!(Selector::is_empty(selector).to_expression().is_ok());
+    }
+}
+
+#[test]
+fn filter_invalid_selector_test() {
+    use consul::catalog::Catalog;
+    for selector in [
+        "",
+        "Service..Tags",
+        "Service.Tags or 1",
+        "1Service",
+        "_Service",
+        "Service.my-key",
+        r#"Service.Meta["my-key"#,
+        r#"Service.Meta["a"]x"#,
+        "Service.Meta[key]",
+    ]
+    .iter()
+    {
+        assert!(Selector::is_empty(selector).to_expression().is_err());
+    }
+    // No request is sent for an invalid filter
+    let transport = ScriptedTransport::new(&[]);
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
+    let client = Client::new(config);
+    let options = QueryOptions {
+        filter: Some(Selector::eq("Node Name", "foo")),
+        ..Default::default()
+    };
+    assert!(client.nodes(Some(&options)).is_err());
+    assert!(transport.requests().is_empty());
+}
+
+#[test]
+fn filter_query_param_test() {
+    use consul::agent::Agent;
+    let (address, server) =
+        serve_once("HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}");
+    let mut config = Config::new().unwrap();
+    config.address = address;
+    let client = Client::new(config);
+
+    let options = QueryOptions {
+        filter: Some(Selector::contains("Tags", "canary")),
+        ..Default::default()
+    };
+    let (services, _) = client.services(Some(&options)).unwrap();
+    assert!(services.is_empty());
+    assert!(server
+        .join()
+        .unwrap()
+        .contains("filter=Tags+contains+%22canary%22"));
+}
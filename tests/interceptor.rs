@@ -0,0 +1,160 @@
+extern crate consul;
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::Method;
+
+use common::ScriptedTransport;
+use consul::interceptor::{Interceptor, RequestContext, RequestInfo, ResponseInfo};
+use consul::kv::KV;
+use consul::{Client, Config, QueryOptions, RetryPolicy};
+
+/// Status, index and attempts of a response.
+type Outcome = (Option<u16>, Option<u64>, u32);
+
+/// Records the requests and the status, index and attempts of their
+/// responses, along with the context passed to each response.
+#[derive(Debug, Default)]
+struct Recorder {
+    requests: Mutex<Vec<RequestInfo>>,
+    responses: Mutex<Vec<Outcome>>,
+    contexts: Mutex<Vec<Option<usize>>>,
+}
+
+impl Interceptor for Recorder {
+    fn on_request(&self, request: &RequestInfo) -> RequestContext {
+        let mut requests = self.requests.lock().unwrap();
+        requests.push(request.clone());
+        RequestContext::new(requests.len())
+    }
+
+    fn on_response(&self, _: &RequestInfo, response: &ResponseInfo, context: RequestContext) {
+        self.contexts.lock().unwrap().push(context.take());
+        self.responses.lock().unwrap().push((
+            response.status.map(|s| s.as_u16()),
+            response.index,
+            response.attempts,
+        ));
+    }
+}
+
+fn client(transport: &Arc<ScriptedTransport>) -> (Client, Arc<Recorder>) {
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
+    config.token = Some(String::from("secret"));
+    config.retry = RetryPolicy {
+        initial_backoff: Duration::from_millis(1),
+        ..Default::default()
+    };
+    let recorder = Arc::new(Recorder::default());
+    let client = Client::new(config).with_interceptor(recorder.clone());
+    (client, recorder)
+}
+
+#[test]
+fn interceptor_query_test() {
+    let transport = ScriptedTransport::new(&[(500, "No cluster leader"), (200, "[]")]);
+    let (client, recorder) = client(&transport);
+    let options = QueryOptions {
+        datacenter: Some(String::from("dc2")),
+        ..Default::default()
+    };
+    client.list("foo", Some(&options)).unwrap();
+
+    let requests = recorder.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, Method::GET);
+    assert_eq!(requests[0].path, "/v1/kv/foo");
+    let mut params = requests[0].params.clone();
+    params.sort();
+    assert_eq!(
+        params,
+        vec![
+            (String::from("dc"), String::from("dc2")),
+            (String::from("recurse"), String::new()),
+        ]
+    );
+    assert_eq!(
+        *recorder.responses.lock().unwrap(),
+        [(Some(200), Some(42), 2)]
+    );
+    assert_eq!(*recorder.contexts.lock().unwrap(), [Some(1)]);
+}
+
+#[test]
+fn interceptor_write_error_test() {
+    let transport = ScriptedTransport::new(&[(403, "ACL not found")]);
+    let (client, recorder) = client(&transport);
+    client.delete("foo", None).unwrap_err();
+
+    let requests = recorder.requests.lock().unwrap();
+    assert_eq!(requests[0].method, Method::DELETE);
+    assert_eq!(
+        *recorder.responses.lock().unwrap(),
+        [(Some(403), Some(42), 1)]
+    );
+}
+
+#[test]
+fn interceptor_transport_error_test() {
+    let mut config = Config::new().unwrap();
+    config.address = String::from("http://127.0.0.1:1");
+    config.retry = RetryPolicy::none();
+    let recorder = Arc::new(Recorder::default());
+    let client = Client::new(config).with_interceptor(recorder.clone());
+    client.list("foo", None).unwrap_err();
+    assert_eq!(*recorder.responses.lock().unwrap(), [(None, None, 1)]);
+    assert_eq!(*recorder.contexts.lock().unwrap(), [Some(1)]);
+}
+
+/// Keeps the level and message of each log record.
+#[cfg(feature = "log")]
+struct CapturedLog(Mutex<Vec<(log::Level, String)>>);
+
+#[cfg(feature = "log")]
+impl log::Log for CapturedLog {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let entry = (record.level(), record.args().to_string());
+        self.0.lock().unwrap().push(entry);
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(all(feature = "log", feature = "tracing"))]
+#[test]
+fn interceptor_builtin_test() {
+    use consul::interceptor::{LogInterceptor, TracingInterceptor};
+    static LOG: CapturedLog = CapturedLog(Mutex::new(Vec::new()));
+    log::set_logger(&LOG).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let transport = ScriptedTransport::new(&[(200, "[]"), (403, "ACL not found")]);
+    let (client, _) = client(&transport);
+    let client = client
+        .with_interceptor(Arc::new(LogInterceptor::default()))
+        .with_interceptor(Arc::new(TracingInterceptor));
+    client.list("foo", None).unwrap();
+    client.delete("foo", None).unwrap_err();
+
+    // Only the records of the interceptor, the tracing events being
+    // forwarded to the logger as well
+    let records: Vec<_> = LOG
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, message)| message.contains("/v1/kv/foo"))
+        .map(|(level, message)| (*level, message.contains("-> 403")))
+        .collect();
+    assert_eq!(
+        records,
+        [(log::Level::Debug, false), (log::Level::Warn, true)]
+    );
+}
@@ -1,32 +1,449 @@
 extern crate consul;
-use consul::kv::KVPair;
-use consul::{Client, Config};
+mod common;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_derive::{Deserialize, Serialize};
+
+use common::ScriptedTransport;
+use consul::errors::ErrorKind;
+use consul::kv::{Codec, DeleteOptions, KVPair};
+use consul::test_support::FakeConsul;
+use consul::{Client, Config, QueryOptions};
 
 #[test]
 fn kv_test() {
     use consul::kv::KV;
-    let config = Config::new().unwrap();
+    let consul = FakeConsul::start().unwrap();
+    let config = consul.config().unwrap();
     let client = Client::new(config);
     let r = client.list("", None).unwrap();
     assert!(r.0.is_empty());
 
     let pair = KVPair {
         Key: String::from("testkey"),
-        Value: String::from("testvalue"),
+        Value: b"testvalue".to_vec(),
         ..Default::default()
     };
 
     assert!(client.put(&pair, None).unwrap().0);
 
-    let b64val = client.get("testkey", None).unwrap().0.unwrap().Value;
-    let bytes = base64::decode(b64val).unwrap();
-    assert_eq!(std::str::from_utf8(&bytes).unwrap(), "\"testvalue\"");
+    let value = client.get("testkey", None).unwrap().0.unwrap().Value;
+    assert_eq!(value, b"testvalue");
+    let (raw, _) = client.get_raw("testkey", None).unwrap();
+    assert_eq!(raw.unwrap(), b"testvalue");
 
     let r = client.list("t", None).unwrap();
     assert!(!r.0.is_empty());
 
     client.delete("testkey", None).unwrap();
+    assert!(client.get("testkey", None).unwrap().0.is_none());
+    assert!(client.get_raw("testkey", None).unwrap().0.is_none());
 
     let r = client.list("", None).unwrap();
     assert!(r.0.is_empty());
 }
+
+#[test]
+fn kv_binary_test() {
+    use consul::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+
+    // Every byte value, not valid UTF-8
+    let blob: Vec<u8> = (0..=255).rev().collect();
+    let pair = KVPair {
+        Key: String::from("blob"),
+        Value: blob.clone(),
+        ..Default::default()
+    };
+    assert!(client.put(&pair, None).unwrap().0);
+
+    assert_eq!(client.get("blob", None).unwrap().0.unwrap().Value, blob);
+    assert_eq!(client.get_raw("blob", None).unwrap().0.unwrap(), blob);
+    assert_eq!(client.list("blob", None).unwrap().0[0].Value, blob);
+}
+
+#[test]
+fn kv_trait_object_test() {
+    use consul::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    // The generic methods are left out of the trait object
+    let kv: Box<dyn KV> = Box::new(Client::new(consul.config().unwrap()));
+
+    let pair = KVPair {
+        Key: String::from("mocked"),
+        Value: b"value".to_vec(),
+        ..Default::default()
+    };
+    assert!(kv.put(&pair, None).unwrap().0);
+    assert_eq!(kv.get("mocked", None).unwrap().0.unwrap().Value, b"value");
+}
+
+#[test]
+fn kv_cas_test() {
+    use consul::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+
+    let pair = KVPair {
+        Key: String::from("config"),
+        Value: b"v1".to_vec(),
+        ..Default::default()
+    };
+    client.put_cas(&pair, None).unwrap();
+    // Without an index, the key must not exist yet
+    let err = client.put_cas(&pair, None).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::CasLost(key) if key == "config"));
+
+    let read = client.get("config", None).unwrap().0.unwrap();
+    let update = KVPair {
+        Value: b"v2".to_vec(),
+        ..read.clone()
+    };
+    client.put_cas(&update, None).unwrap();
+
+    // The pair read before the update is stale
+    let stale = KVPair {
+        Value: b"v3".to_vec(),
+        ..read.clone()
+    };
+    let err = client.put_cas(&stale, None).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::CasLost(_)));
+    let err = client.delete_cas(&read, None).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::CasLost(_)));
+    assert_eq!(client.get("config", None).unwrap().0.unwrap().Value, b"v2");
+
+    // A pair without index cannot be checked
+    let unread = KVPair {
+        ModifyIndex: None,
+        ..read.clone()
+    };
+    let err = client.delete_cas(&unread, None).unwrap_err();
+    assert!(err.to_string().contains("no ModifyIndex"));
+    assert_eq!(client.get("config", None).unwrap().0.unwrap().Value, b"v2");
+
+    let current = client.get("config", None).unwrap().0.unwrap();
+    client.delete_cas(&current, None).unwrap();
+    assert!(client.get("config", None).unwrap().0.is_none());
+}
+
+#[test]
+fn kv_update_test() {
+    use consul::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Arc::new(Client::new(consul.config().unwrap()));
+
+    let increment = |pair: Option<&KVPair>| {
+        let count: u32 = match pair {
+            Some(pair) => String::from_utf8_lossy(&pair.Value).parse().unwrap(),
+            None => 0,
+        };
+        Ok((count + 1).to_string().into_bytes())
+    };
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let client = client.clone();
+            thread::spawn(move || {
+                for _ in 0..5 {
+                    client.update("counter", 100, increment, None).unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let (value, _) = client.get_raw("counter", None).unwrap();
+    assert_eq!(value.unwrap(), b"20");
+
+    // The closure errors are returned as is
+    let err = client
+        .update("counter", 1, |_| Err("invalid counter".into()), None)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "invalid counter");
+}
+
+#[test]
+fn kv_update_budget_test() {
+    use consul::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let other = Client::new(consul.config().unwrap());
+
+    // A concurrent write between each read and write
+    let mut calls = 0;
+    let err = client
+        .update(
+            "contended",
+            3,
+            |_| {
+                calls += 1;
+                let pair = KVPair {
+                    Key: String::from("contended"),
+                    Value: calls.to_string().into_bytes(),
+                    ..Default::default()
+                };
+                other.put(&pair, None).unwrap();
+                Ok(b"mine".to_vec())
+            },
+            None,
+        )
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::CasLost(_)));
+    assert_eq!(calls, 3);
+}
+
+#[test]
+fn kv_keys_test() {
+    use consul::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Arc::new(Client::new(consul.config().unwrap()));
+    for key in ["app/db/url", "app/db/user", "app/name", "other"].iter() {
+        let pair = KVPair {
+            Key: key.to_string(),
+            Value: b"value".to_vec(),
+            ..Default::default()
+        };
+        client.put(&pair, None).unwrap();
+    }
+
+    let (keys, _) = client.keys("app/", None, None).unwrap();
+    assert_eq!(keys, ["app/db/url", "app/db/user", "app/name"]);
+    let (keys, _) = client.keys("", Some("/"), None).unwrap();
+    assert_eq!(keys, ["app/", "other"]);
+    let (keys, meta) = client.keys("app/", Some("/"), None).unwrap();
+    assert_eq!(keys, ["app/db/", "app/name"]);
+    let (keys, _) = client.keys("missing/", Some("/"), None).unwrap();
+    assert!(keys.is_empty());
+
+    // Blocking query returning once a key is added
+    let writer = client.clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        let pair = KVPair {
+            Key: String::from("app/port"),
+            ..Default::default()
+        };
+        writer.put(&pair, None).unwrap();
+    });
+    let options = QueryOptions {
+        wait_index: meta.last_index,
+        wait_time: Some(Duration::from_secs(10)),
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let (keys, _) = client.keys("app/", Some("/"), Some(&options)).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!(keys, ["app/db/", "app/name", "app/port"]);
+    handle.join().unwrap();
+}
+
+#[test]
+fn kv_delete_with_test() {
+    use consul::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let pair_of = |key: &str| KVPair {
+        Key: key.to_owned(),
+        ..Default::default()
+    };
+    for key in ["svc/a", "svc/b/c", "svcx", "other"].iter() {
+        client.put(&pair_of(key), None).unwrap();
+    }
+
+    let recurse = DeleteOptions {
+        recurse: true,
+        ..Default::default()
+    };
+    let (removed, _) = client.delete_with("svc/", &recurse, None).unwrap();
+    assert!(removed);
+    let (keys, _) = client.keys("", None, None).unwrap();
+    assert_eq!(keys, ["other", "svcx"]);
+    let (removed, _) = client.delete_with("svc/", &recurse, None).unwrap();
+    assert!(!removed);
+
+    // Single keys, possibly with a check-and-set
+    let single = DeleteOptions::default();
+    let (removed, _) = client.delete_with("svc", &single, None).unwrap();
+    assert!(!removed);
+    let pair = client.get("svcx", None).unwrap().0.unwrap();
+    let stale = DeleteOptions {
+        cas: Some(pair.ModifyIndex.unwrap() - 1),
+        ..Default::default()
+    };
+    let err = client.delete_with("svcx", &stale, None).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::CasLost(_)));
+    let cas = DeleteOptions {
+        cas: pair.ModifyIndex,
+        ..Default::default()
+    };
+    let (removed, _) = client.delete_with("svcx", &cas, None).unwrap();
+    assert!(removed);
+    let (removed, _) = client.delete_with("svcx", &cas, None).unwrap();
+    assert!(!removed);
+    let recurse_cas = DeleteOptions {
+        cas: Some(1),
+        ..recurse.clone()
+    };
+    client.delete_with("other", &recurse_cas, None).unwrap_err();
+
+    client.put(&pair_of("single"), None).unwrap();
+    let (removed, _) = client.delete_with("single", &single, None).unwrap();
+    assert!(removed);
+    let (removed, _) = client.delete_with("single", &single, None).unwrap();
+    assert!(!removed);
+
+    // The whole store is only deleted when confirmed
+    client.delete_with("", &recurse, None).unwrap_err();
+    client.delete_with("", &single, None).unwrap_err();
+    assert_eq!(client.keys("", None, None).unwrap().0, ["other"]);
+    let all = DeleteOptions {
+        confirm_delete_all: true,
+        ..recurse
+    };
+    let (removed, _) = client.delete_with("", &all, None).unwrap();
+    assert!(removed);
+    assert!(client.keys("", None, None).unwrap().0.is_empty());
+}
+
+#[test]
+fn kv_delete_with_requests_test() {
+    use consul::kv::KV;
+    let transport = ScriptedTransport::new(&[
+        (200, r#"{"Results":[{"KV":{"Key":"svc/a"}}],"Errors":null}"#),
+        (200, r#"{"Results":[{"KV":{"Key":"svc/a"}}],"Errors":null}"#),
+        (
+            409,
+            r#"{"Results":null,"Errors":[{"OpIndex":1,"What":"index is stale"}]}"#,
+        ),
+    ]);
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
+    let client = Client::new(config);
+    let verbs = |index: usize| -> Vec<serde_json::Value> {
+        let body = transport.requests()[index].body.clone().unwrap();
+        let ops: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        ops.iter().map(|op| op["KV"]["Verb"].clone()).collect()
+    };
+
+    // The keys are read in the transaction deleting them
+    let recurse = DeleteOptions {
+        recurse: true,
+        ..Default::default()
+    };
+    assert!(client.delete_with("svc/", &recurse, None).unwrap().0);
+    assert_eq!(transport.requests()[0].url.path(), "/v1/txn");
+    assert_eq!(verbs(0), ["get-tree", "delete-tree"]);
+
+    // A single key is only removed when read, not for keys it prefixes
+    let single = DeleteOptions::default();
+    assert!(!client.delete_with("svc", &single, None).unwrap().0);
+    assert_eq!(verbs(1), ["get-tree", "delete"]);
+
+    let cas = DeleteOptions {
+        cas: Some(7),
+        ..Default::default()
+    };
+    let err = client.delete_with("svc", &cas, None).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::CasLost(_)));
+    assert_eq!(verbs(2), ["get-tree", "delete-cas"]);
+    assert_eq!(transport.requests().len(), 3);
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Settings {
+    name: String,
+    replicas: u32,
+    tags: Vec<String>,
+}
+
+fn typed_round_trip(codec: Codec) {
+    use consul::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let settings = Settings {
+        name: String::from("web"),
+        replicas: 3,
+        tags: vec![String::from("blue")],
+    };
+    client
+        .put_typed("web/settings", &settings, codec, None, None)
+        .unwrap();
+
+    let (typed, _) = client
+        .get_typed::<Settings>("web/settings", codec, None)
+        .unwrap();
+    let typed = typed.unwrap();
+    assert_eq!(typed.value, settings);
+
+    // The index of the read feeds a check-and-set write
+    let scaled = Settings {
+        replicas: 5,
+        ..typed.value
+    };
+    let index = typed.pair.ModifyIndex;
+    client
+        .put_typed("web/settings", &scaled, codec, index, None)
+        .unwrap();
+    let err = client
+        .put_typed("web/settings", &scaled, codec, index, None)
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::CasLost(_)));
+    let (typed, _) = client
+        .get_typed::<Settings>("web/settings", codec, None)
+        .unwrap();
+    assert_eq!(typed.unwrap().value, scaled);
+    let (missing, _) = client
+        .get_typed::<Settings>("web/missing", codec, None)
+        .unwrap();
+    assert!(missing.is_none());
+}
+
+#[test]
+fn kv_typed_test() {
+    use consul::kv::KV;
+    typed_round_trip(Codec::default());
+
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let pair = KVPair {
+        Key: String::from("web/settings"),
+        Value: b"replicas: 3".to_vec(),
+        ..Default::default()
+    };
+    client.put(&pair, None).unwrap();
+    let err = client
+        .get_typed::<Settings>("web/settings", Codec::Json, None)
+        .unwrap_err();
+    assert!(
+        matches!(err.kind(), ErrorKind::ValueDecode(key, codec) if key == "web/settings" && codec == "JSON")
+    );
+    assert_eq!(
+        err.to_string(),
+        "Failed to decode the value of 'web/settings' as JSON"
+    );
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn kv_typed_yaml_test() {
+    typed_round_trip(Codec::Yaml);
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn kv_typed_toml_test() {
+    typed_round_trip(Codec::Toml);
+
+    use consul::kv::KV;
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    // Unlike JSON, TOML has no null values
+    let err = client
+        .put_typed("web/replicas", &None::<u32>, Codec::Toml, None, None)
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::ValueEncode(_, codec) if codec == "TOML"));
+}
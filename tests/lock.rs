@@ -0,0 +1,114 @@
+extern crate consul;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use consul::kv::{KVPair, KV};
+use consul::lock::{Lock, LockOptions, LOCK_FLAG_VALUE};
+use consul::session::{Session, SessionEntry};
+use consul::test_support::FakeConsul;
+use consul::Client;
+
+fn lock(consul: &FakeConsul, key: &str) -> Lock {
+    let client = Client::new(consul.config().unwrap());
+    let options = LockOptions {
+        value: b"holder".to_vec(),
+        ..LockOptions::new(key)
+    };
+    Lock::new(client, options)
+}
+
+#[test]
+fn lock_exclusive_test() {
+    let consul = FakeConsul::start().unwrap();
+    let (first, second) = (lock(&consul, "jobs/lock"), lock(&consul, "jobs/lock"));
+
+    let guard = first.try_lock().unwrap().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let pair = client.get("jobs/lock", None).unwrap().0.unwrap();
+    assert_eq!(pair.Session.as_deref(), Some(guard.session()));
+    assert_eq!(pair.Flags, Some(LOCK_FLAG_VALUE));
+    assert_eq!(pair.Value, b"holder");
+
+    assert!(first.try_lock().is_err());
+    assert!(second.try_lock().unwrap().is_none());
+    let start = Instant::now();
+    assert!(second
+        .lock(Some(Duration::from_millis(300)))
+        .unwrap()
+        .is_none());
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    // The lock created the session, and destroys it once released
+    let session = guard.session().to_owned();
+    guard.unlock().unwrap();
+    assert!(client.info(&session, None).unwrap().0.is_empty());
+    assert!(second.try_lock().unwrap().is_some());
+    assert!(first.try_lock().unwrap().is_some());
+}
+
+#[test]
+fn lock_wait_test() {
+    let consul = FakeConsul::start().unwrap();
+    let (first, second) = (lock(&consul, "jobs/lock"), lock(&consul, "jobs/lock"));
+
+    let guard = first.lock(None).unwrap().unwrap();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(300));
+        drop(guard);
+    });
+    let start = Instant::now();
+    let guard = second.lock(Some(Duration::from_secs(10))).unwrap();
+    assert!(guard.is_some());
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    handle.join().unwrap();
+}
+
+#[test]
+fn lock_lost_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+
+    let first = lock(&consul, "jobs/lock");
+    let guard = first.lock(None).unwrap().unwrap();
+    assert!(!guard.lost().wait(Some(Duration::from_millis(100))));
+    client.delete("jobs/lock", None).unwrap();
+    assert!(guard.lost().wait(Some(Duration::from_secs(5))));
+    drop(guard);
+
+    let guard = first.lock(None).unwrap().unwrap();
+    client.destroy(guard.session(), None).unwrap();
+    assert!(guard.lost().wait(Some(Duration::from_secs(5))));
+    drop(guard);
+
+    // A session given to the lock is not renewed by it
+    let entry = SessionEntry {
+        TTL: Some(String::from("1s")),
+        ..Default::default()
+    };
+    let session = client.create(&entry, None).unwrap().0.ID;
+    let options = LockOptions {
+        session: session.clone(),
+        ..LockOptions::new("jobs/lock")
+    };
+    let lock = Lock::new(client.clone(), options);
+    let guard = lock.lock(None).unwrap().unwrap();
+    assert_eq!(Some(guard.session()), session.as_deref());
+    assert!(guard.lost().wait(Some(Duration::from_secs(5))));
+}
+
+#[test]
+fn lock_conflict_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let pair = KVPair {
+        Key: String::from("jobs/lock"),
+        Value: b"config".to_vec(),
+        ..Default::default()
+    };
+    client.put(&pair, None).unwrap();
+
+    let err = lock(&consul, "jobs/lock").try_lock().err().unwrap();
+    assert_eq!(err.to_string(), "The key 'jobs/lock' is not used as a lock");
+    // The session created for the attempt is destroyed
+    assert!(Session::list(&client, None).unwrap().0.is_empty());
+}
@@ -0,0 +1,123 @@
+extern crate consul;
+mod common;
+
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::ScriptedTransport;
+use consul::errors::{Error, ErrorKind, Result};
+use consul::kv::KVPair;
+use consul::transport::{HttpRequest, HttpResponse, Transport};
+use consul::{Client, Config, RetryPolicy, WriteOptions};
+
+#[test]
+fn retry_backoff_test() {
+    let policy = RetryPolicy {
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_millis(350),
+        ..Default::default()
+    };
+    for (attempt, max) in [(1, 100), (2, 200), (3, 350), (10, 350)].iter() {
+        let backoff = policy.backoff(*attempt);
+        assert!(backoff <= Duration::from_millis(*max));
+        assert!(backoff >= Duration::from_millis(*max / 2));
+    }
+}
+
+#[test]
+fn retry_connection_refused_test() {
+    use consul::catalog::Catalog;
+    let mut config = Config::new().unwrap();
+    config.address = String::from("http://127.0.0.1:1");
+    config.retry = RetryPolicy {
+        max_attempts: 2,
+        initial_backoff: Duration::from_millis(1),
+        ..Default::default()
+    };
+    let client = Client::new(config);
+    let err = client.datacenters().unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Transport));
+}
+
+/// Fails every request with the given error, counting them.
+#[derive(Debug)]
+struct FailingTransport {
+    transient: bool,
+    attempts: AtomicU32,
+}
+
+impl Transport for FailingTransport {
+    fn send(&self, _: HttpRequest) -> Result<HttpResponse> {
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+        if self.transient {
+            let timeout = io::Error::new(io::ErrorKind::TimedOut, "timed out");
+            Err(Error::with_chain(timeout, ErrorKind::Transport))
+        } else {
+            Err(Error::from("invalid peer certificate"))
+        }
+    }
+}
+
+fn retrying_client(transport: Arc<dyn Transport>, max_attempts: u32) -> Client {
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport);
+    config.retry = RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(1),
+        ..Default::default()
+    };
+    Client::new(config)
+}
+
+#[test]
+fn retry_writes_test() {
+    use consul::kv::KV;
+    let pair = KVPair {
+        Key: String::from("foo"),
+        ..Default::default()
+    };
+    let transport = ScriptedTransport::new(&[(500, "No cluster leader"), (503, "")]);
+    let client = retrying_client(transport.clone(), 3);
+    client.put(&pair, None).unwrap_err();
+    client.delete("foo", None).unwrap_err();
+    assert_eq!(transport.requests().len(), 2);
+
+    // Unless opted in
+    let transport = ScriptedTransport::new(&[(500, "No cluster leader"), (503, ""), (200, "true")]);
+    let client = retrying_client(transport.clone(), 3);
+    let options = WriteOptions {
+        retry: true,
+        ..Default::default()
+    };
+    let (_, meta) = client.put(&pair, Some(&options)).unwrap();
+    assert_eq!(meta.attempts, 3);
+    assert_eq!(transport.requests().len(), 3);
+}
+
+#[test]
+fn retry_max_attempts_test() {
+    use consul::kv::KV;
+    for max_attempts in 1..=4 {
+        let transport = ScriptedTransport::new(&[(503, ""); 4]);
+        let client = retrying_client(transport.clone(), max_attempts);
+        let err = client.list("foo", None).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ConsulError(503, _)));
+        assert_eq!(transport.requests().len(), max_attempts as usize);
+    }
+}
+
+#[test]
+fn retry_transient_errors_test() {
+    use consul::kv::KV;
+    for (transient, attempts) in [(true, 3), (false, 1)].iter() {
+        let transport = Arc::new(FailingTransport {
+            transient: *transient,
+            attempts: AtomicU32::new(0),
+        });
+        let client = retrying_client(transport.clone(), 3);
+        client.list("foo", None).unwrap_err();
+        assert_eq!(transport.attempts.load(Ordering::SeqCst), *attempts);
+    }
+}
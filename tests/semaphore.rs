@@ -0,0 +1,118 @@
+extern crate consul;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use consul::kv::{KVPair, KV};
+use consul::semaphore::{Semaphore, SemaphoreOptions, SEMAPHORE_FLAG_VALUE};
+use consul::session::Session;
+use consul::test_support::FakeConsul;
+use consul::Client;
+
+fn semaphore(consul: &FakeConsul, limit: usize) -> Semaphore {
+    let client = Client::new(consul.config().unwrap());
+    Semaphore::new(client, SemaphoreOptions::new("jobs/slots/", limit))
+}
+
+fn holders(client: &Client) -> serde_json::Value {
+    let pair = client.get("jobs/slots/.lock", None).unwrap().0.unwrap();
+    assert_eq!(pair.Flags, Some(SEMAPHORE_FLAG_VALUE));
+    serde_json::from_slice::<serde_json::Value>(&pair.Value).unwrap()["Holders"].clone()
+}
+
+#[test]
+fn semaphore_limit_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let semaphores: Vec<_> = (0..3).map(|_| semaphore(&consul, 2)).collect();
+
+    let first = semaphores[0].try_acquire().unwrap().unwrap();
+    let second = semaphores[1].acquire(None).unwrap().unwrap();
+    assert!(semaphores[0].try_acquire().is_err());
+    let start = Instant::now();
+    assert!(semaphores[2]
+        .acquire(Some(Duration::from_millis(300)))
+        .unwrap()
+        .is_none());
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    // The contender key of the attempt is removed with its session
+    assert_eq!(KV::list(&client, "jobs/slots/", None).unwrap().0.len(), 3);
+
+    let slots = holders(&client);
+    assert_eq!(slots.as_object().unwrap().len(), 2);
+    assert_eq!(slots[first.session()], true);
+    let pair = client
+        .get(&format!("jobs/slots/{}", second.session()), None)
+        .unwrap()
+        .0
+        .unwrap();
+    assert_eq!(pair.Session.as_deref(), Some(second.session()));
+
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(300));
+        first.release().unwrap();
+    });
+    let third = semaphores[2]
+        .acquire(Some(Duration::from_secs(10)))
+        .unwrap();
+    assert!(third.is_some());
+    handle.join().unwrap();
+    drop((second, third));
+    assert!(Session::list(&client, None).unwrap().0.is_empty());
+    assert!(holders(&client).as_object().unwrap().is_empty());
+}
+
+#[test]
+fn semaphore_prune_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let (first, second) = (semaphore(&consul, 1), semaphore(&consul, 1));
+
+    let guard = first.acquire(None).unwrap().unwrap();
+    assert!(!guard.lost().wait(Some(Duration::from_millis(100))));
+    // The slot of a destroyed session is freed for the other contenders
+    client.destroy(guard.session(), None).unwrap();
+    assert!(guard.lost().wait(Some(Duration::from_secs(5))));
+    let other = second.try_acquire().unwrap().unwrap();
+    assert_eq!(
+        holders(&client)
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        [other.session()]
+    );
+    drop(guard);
+    assert!(!other.lost().wait(Some(Duration::from_millis(100))));
+
+    // Removing the session from the holders loses the slot too
+    client.delete("jobs/slots/.lock", None).unwrap();
+    assert!(other.lost().wait(Some(Duration::from_secs(5))));
+}
+
+#[test]
+fn semaphore_conflict_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+
+    let guard = semaphore(&consul, 2).try_acquire().unwrap().unwrap();
+    let err = semaphore(&consul, 3).try_acquire().err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "The limit 3 of the semaphore on 'jobs/slots' does not match the limit 2 of its holders"
+    );
+    drop(guard);
+
+    let pair = KVPair {
+        Key: String::from("jobs/slots/config"),
+        Value: b"config".to_vec(),
+        ..Default::default()
+    };
+    client.put(&pair, None).unwrap();
+    let err = semaphore(&consul, 2).try_acquire().err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "The prefix 'jobs/slots' is not used as a semaphore"
+    );
+    // The sessions created for the attempts are destroyed
+    assert!(Session::list(&client, None).unwrap().0.is_empty());
+}
@@ -1,7 +1,14 @@
 extern crate consul;
 extern crate rand;
+mod common;
 
-use consul::session::{Session, SessionEntry};
+use std::thread;
+use std::time::Duration;
+
+use common::ScriptedTransport;
+use consul::errors::ErrorKind;
+use consul::session::{renew_periodic, Session, SessionEntry};
+use consul::test_support::FakeConsul;
 use consul::{Client, Config};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
@@ -9,7 +16,7 @@ use rstest::*;
 
 #[rstest]
 fn session_create_test() {
-    let (client, unique_test_identifier) = set_up();
+    let (_consul, client, unique_test_identifier) = set_up();
 
     assert_eq!(
         get_number_of_session_entries_with_matching_name(&client, &unique_test_identifier),
@@ -33,7 +40,7 @@ fn session_create_test() {
 
 #[rstest]
 fn session_destroy_test() {
-    let (client, unique_test_identifier) = set_up();
+    let (_consul, client, unique_test_identifier) = set_up();
 
     let entry = SessionEntry {
         Name: Some(unique_test_identifier.to_string()),
@@ -61,7 +68,7 @@ fn session_destroy_test() {
 
 #[rstest]
 fn session_info_test() {
-    let (client, unique_test_identifier) = set_up();
+    let (_consul, client, unique_test_identifier) = set_up();
 
     let entry = SessionEntry {
         Name: Some(unique_test_identifier.to_string()),
@@ -76,7 +83,7 @@ fn session_info_test() {
 
     assert_eq!(session_entries.len(), 1);
 
-    let session_entry = session_entries.get(0);
+    let session_entry = session_entries.first();
 
     assert_eq!(
         *session_entry.as_ref().unwrap().Name.as_ref().unwrap(),
@@ -88,7 +95,7 @@ fn session_info_test() {
 
 #[rstest]
 fn session_list_test() {
-    let (client, unique_test_identifier) = set_up();
+    let (_consul, client, unique_test_identifier) = set_up();
 
     let entry_names = vec![
         format!("{}-1", unique_test_identifier),
@@ -134,7 +141,7 @@ fn session_list_test() {
 
 #[rstest]
 fn session_node_test() {
-    let (client, unique_test_identifier) = set_up();
+    let (_consul, client, unique_test_identifier) = set_up();
 
     let entry = SessionEntry {
         Name: Some(unique_test_identifier.to_string()),
@@ -161,7 +168,7 @@ fn session_node_test() {
 
 #[rstest]
 fn session_renew_test() {
-    let (client, unique_test_identifier) = set_up();
+    let (_consul, client, unique_test_identifier) = set_up();
 
     let entry = SessionEntry {
         Name: Some(unique_test_identifier),
@@ -177,17 +184,78 @@ fn session_renew_test() {
     tear_down(&client, &created_session_entry_id);
 }
 
-fn set_up() -> (Client, String) {
-    let config = Config::new().unwrap();
+#[rstest]
+fn session_renew_periodic_test() {
+    let (_consul, client, unique_test_identifier) = set_up();
+
+    let entry = SessionEntry {
+        Name: Some(unique_test_identifier),
+        TTL: Some(String::from("1s")),
+        ..Default::default()
+    };
+    let id = client.create(&entry, None).unwrap().0.ID.unwrap();
+
+    let renewer = renew_periodic(&client, &id, Duration::from_secs(1), true, None).unwrap();
+    thread::sleep(Duration::from_millis(2500));
+    assert_eq!(client.info(&id, None).unwrap().0.len(), 1);
+    assert!(renewer.gone().try_recv().is_err());
+    drop(renewer);
+    assert!(client.info(&id, None).unwrap().0.is_empty());
+
+    let id = client.create(&entry, None).unwrap().0.ID.unwrap();
+    let renewer = renew_periodic(&client, &id, Duration::from_secs(1), false, None).unwrap();
+    renewer.stop().unwrap();
+    assert_eq!(client.info(&id, None).unwrap().0.len(), 1);
+    assert!(renew_periodic(&client, &id, Duration::from_secs(0), false, None).is_err());
+
+    tear_down(&client, &id);
+}
+
+#[rstest]
+fn session_renew_periodic_gone_test() {
+    let (_consul, client, unique_test_identifier) = set_up();
+
+    let entry = SessionEntry {
+        Name: Some(unique_test_identifier),
+        TTL: Some(String::from("1s")),
+        ..Default::default()
+    };
+    let id = client.create(&entry, None).unwrap().0.ID.unwrap();
+    let renewer = renew_periodic(&client, &id, Duration::from_secs(1), true, None).unwrap();
+
+    client.destroy(&id, None).unwrap();
+    let err = renewer.gone().recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(matches!(err.kind(), ErrorKind::NotFound(_)));
+}
+
+#[rstest]
+fn session_renew_periodic_ttl_test() {
+    let transport = ScriptedTransport::new(&[(200, r#"[{"ID":"s1","TTL":"1m0s"}]"#)]);
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
     let client = Client::new(config);
 
+    // The renewer follows the TTL returned by Consul
+    let renewer = renew_periodic(&client, "s1", Duration::from_millis(200), false, None).unwrap();
+    thread::sleep(Duration::from_millis(800));
+    drop(renewer);
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].url.path(), "/v1/session/renew/s1");
+}
+
+fn set_up() -> (FakeConsul, Client, String) {
+    let system_hostname = hostname::get().unwrap().into_string().unwrap();
+    let consul = FakeConsul::start_with_node(&system_hostname).unwrap();
+    let client = Client::new(consul.config().unwrap());
+
     let unique_test_identifier: String = thread_rng()
         .sample_iter(&Alphanumeric)
         .take(16)
         .map(char::from)
         .collect();
 
-    (client, unique_test_identifier)
+    (consul, client, unique_test_identifier)
 }
 
 fn tear_down(client: &Client, session_id: &str) {
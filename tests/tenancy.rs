@@ -0,0 +1,94 @@
+extern crate consul;
+mod common;
+
+use std::collections::HashMap;
+
+use common::ScriptedTransport;
+use consul::health::Health;
+use consul::kv::KV;
+use consul::session::{Session, SessionEntry};
+use consul::{Client, Config, QueryOptions, WriteOptions};
+
+fn query_params(transport: &ScriptedTransport) -> HashMap<String, String> {
+    let requests = transport.requests();
+    requests
+        .last()
+        .unwrap()
+        .url
+        .query_pairs()
+        .into_owned()
+        .collect()
+}
+
+#[test]
+fn tenancy_defaults_test() {
+    let transport = ScriptedTransport::new(&[(200, "[]")]);
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
+    config.namespace = Some(String::from("team"));
+    config.partition = Some(String::from("eu"));
+    let client = Client::new(config);
+
+    client.service("web", None, false, None).unwrap();
+    let params = query_params(&transport);
+    assert_eq!(params["ns"], "team");
+    assert_eq!(params["partition"], "eu");
+    assert!(!params.contains_key("peer"));
+}
+
+#[test]
+fn tenancy_query_override_test() {
+    let transport = ScriptedTransport::new(&[(
+        200,
+        r#"[{"Node":{"Node":"n1"},"Service":{"ID":"web","Service":"web","Namespace":"other","Partition":"us"},
+            "Checks":[{"CheckID":"serfHealth","Namespace":"other","Partition":"us"}]}]"#,
+    )]);
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
+    config.namespace = Some(String::from("team"));
+    config.partition = Some(String::from("eu"));
+    let client = Client::new(config);
+
+    let options = QueryOptions {
+        namespace: Some(String::from("other")),
+        partition: Some(String::from("us")),
+        peer: Some(String::from("cluster-02")),
+        ..Default::default()
+    };
+    let (entries, _) = client.service("web", None, false, Some(&options)).unwrap();
+    let params = query_params(&transport);
+    assert_eq!(params["ns"], "other");
+    assert_eq!(params["partition"], "us");
+    assert_eq!(params["peer"], "cluster-02");
+    assert_eq!(entries[0].Service.Namespace.as_deref(), Some("other"));
+    assert_eq!(entries[0].Checks[0].Partition.as_deref(), Some("us"));
+}
+
+#[test]
+fn tenancy_write_override_test() {
+    let transport = ScriptedTransport::new(&[(200, r#"{"ID":"abc"}"#), (200, "true")]);
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
+    config.namespace = Some(String::from("team"));
+    let client = Client::new(config);
+
+    let options = WriteOptions {
+        partition: Some(String::from("us")),
+        ..Default::default()
+    };
+    client
+        .create(&SessionEntry::default(), Some(&options))
+        .unwrap();
+    let params = query_params(&transport);
+    assert_eq!(params["ns"], "team");
+    assert_eq!(params["partition"], "us");
+
+    let options = WriteOptions {
+        namespace: Some(String::from("other")),
+        ..Default::default()
+    };
+    client.delete("foo", Some(&options)).unwrap();
+    let params = query_params(&transport);
+    assert_eq!(params["ns"], "other");
+    assert!(!params.contains_key("partition"));
+}
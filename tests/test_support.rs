@@ -0,0 +1,60 @@
+extern crate consul;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use consul::health::Health;
+use consul::kv::{KVPair, KV};
+use consul::test_support::FakeConsul;
+use consul::{Client, QueryOptions};
+
+#[test]
+fn fake_blocking_query_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let (_, meta) = client.list("", None).unwrap();
+    let index = meta.last_index.unwrap();
+
+    let writer = Client::new(consul.config().unwrap());
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        let pair = KVPair {
+            Key: String::from("foo"),
+            Value: b"bar".to_vec(),
+            ..Default::default()
+        };
+        writer.put(&pair, None).unwrap();
+    });
+
+    let options = QueryOptions {
+        wait_index: Some(index),
+        wait_time: Some(Duration::from_secs(10)),
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let (pair, meta) = client.get("foo", Some(&options)).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert!(meta.last_index.unwrap() > index);
+    assert_eq!(pair.unwrap().ModifyIndex, meta.last_index);
+    handle.join().unwrap();
+
+    // Without changes, the query returns once its wait time elapsed
+    let options = QueryOptions {
+        wait_index: meta.last_index,
+        wait_time: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+    let (_, unchanged) = client.get("foo", Some(&options)).unwrap();
+    assert_eq!(unchanged.last_index, meta.last_index);
+}
+
+#[test]
+fn fake_health_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let (entries, meta) = client.service("consul", None, true, None).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].Node.Node, consul.node());
+    assert!(meta.known_leader);
+    let (entries, _) = client.service("missing", None, true, None).unwrap();
+    assert!(entries.is_empty());
+}
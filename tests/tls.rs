@@ -0,0 +1,152 @@
+extern crate consul;
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use common::ScriptedTransport;
+use consul::kv::KV;
+use consul::{Client, Config, RetryPolicy, TlsConfig};
+
+fn data(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn client_tls(cert: &str, key: PathBuf) -> TlsConfig {
+    TlsConfig {
+        client_cert: Some(data(cert)),
+        client_key: Some(key),
+        ..Default::default()
+    }
+}
+
+fn server_name_config(address: &str) -> Config {
+    let mut config = Config::new().unwrap();
+    config.address = address.to_owned();
+    config.retry = RetryPolicy::none();
+    let tls = TlsConfig {
+        server_name: Some(String::from("consul.example")),
+        ..Default::default()
+    };
+    config.with_tls(tls).unwrap()
+}
+
+#[test]
+fn tls_missing_ca_file_test() {
+    let tls = TlsConfig {
+        ca_file: Some(PathBuf::from("/non/existing/ca.pem")),
+        ..Default::default()
+    };
+    let err = Config::new().unwrap().with_tls(tls).unwrap_err();
+    assert!(err.to_string().contains("/non/existing/ca.pem"));
+}
+
+#[test]
+fn tls_client_cert_without_key_test() {
+    let tls = TlsConfig {
+        client_cert: Some(PathBuf::from("/non/existing/cert.pem")),
+        ..Default::default()
+    };
+    assert!(Config::new().unwrap().with_tls(tls).is_err());
+}
+
+#[test]
+fn tls_with_tls_keeps_config_test() {
+    let mut config = Config::new().unwrap();
+    config.datacenter = Some(String::from("dc2"));
+    let tls = TlsConfig {
+        verify: false,
+        ..Default::default()
+    };
+    let config = config.with_tls(tls).unwrap();
+    assert_eq!(config.datacenter.as_deref(), Some("dc2"));
+    assert!(!config.tls.verify);
+}
+
+#[test]
+fn tls_pkcs1_and_sec1_keys_test() {
+    for (cert, key) in &[
+        ("rsa-cert.pem", "rsa-key.pem"),
+        ("ec-cert.pem", "ec-key.pem"),
+    ] {
+        let tls = client_tls(cert, data(key));
+        Config::new().unwrap().with_tls(tls).unwrap();
+    }
+}
+
+#[test]
+fn tls_encrypted_key_test() {
+    let key = fs::read_to_string(data("rsa-key.pem")).unwrap().replacen(
+        "-----\n",
+        "-----\nProc-Type: 4,ENCRYPTED\nDEK-Info: AES-128-CBC,00\n\n",
+        1,
+    );
+    let path = std::env::temp_dir().join(format!("consul-rust-key-{}.pem", std::process::id()));
+    fs::write(&path, key).unwrap();
+    let err = Config::new()
+        .unwrap()
+        .with_tls(client_tls("rsa-cert.pem", path.clone()))
+        .unwrap_err();
+    fs::remove_file(&path).unwrap();
+    assert!(err.to_string().contains("openssl pkcs8 -topk8 -nocrypt"));
+}
+
+#[test]
+fn tls_server_name_url_test() {
+    let mut config = server_name_config("https://127.0.0.1:8501");
+    let transport = ScriptedTransport::new(&[(200, "[]")]);
+    config.transport = Some(transport.clone());
+    let client = Client::new(config);
+    client.list("foo", None).unwrap();
+    let url = &transport.requests()[0].url;
+    assert_eq!(url.host_str(), Some("consul.example"));
+    assert_eq!(url.port(), Some(8501));
+}
+
+#[test]
+fn tls_server_name_address_changed_test() {
+    let mut config = server_name_config("https://127.0.0.1:8501");
+    config.address = String::from("https://127.0.0.2:8501");
+    let transport = ScriptedTransport::new(&[(200, "[]")]);
+    config.transport = Some(transport.clone());
+    let err = Client::new(config.clone()).list("foo", None).unwrap_err();
+    assert!(err.to_string().contains("Config::with_tls"));
+    assert!(transport.requests().is_empty());
+
+    let tls = config.tls.clone();
+    Client::new(config.with_tls(tls).unwrap())
+        .list("foo", None)
+        .unwrap();
+    assert_eq!(transport.requests().len(), 1);
+}
+
+#[test]
+fn tls_server_name_handshake_test() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("https://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut hello = vec![0; 4096];
+        let read = stream.read(&mut hello).unwrap();
+        hello.truncate(read);
+        hello
+    });
+
+    // The connection reaches the agent address, with the server name sent
+    // in the TLS client hello
+    let client = Client::new(server_name_config(&address));
+    client.list("foo", None).unwrap_err();
+    let hello = handle.join().unwrap();
+    assert!(hello
+        .windows(b"consul.example".len())
+        .any(|w| w == b"consul.example"));
+}
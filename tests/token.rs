@@ -0,0 +1,61 @@
+extern crate consul;
+mod common;
+
+use common::ScriptedTransport;
+use consul::kv::{KVPair, KV};
+use consul::{Client, Config, QueryOptions, RequestToken, WriteOptions};
+
+fn client(transport: &std::sync::Arc<ScriptedTransport>) -> Client {
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
+    config.token = Some(String::from("config-token"));
+    Client::new(config)
+}
+
+#[test]
+fn token_override_query_test() {
+    let transport = ScriptedTransport::new(&[(200, "[]"), (200, "[]"), (200, "[]")]);
+    let client = client(&transport);
+
+    client.list("foo", None).unwrap();
+    let options = QueryOptions {
+        token: Some(RequestToken::Token(String::from("tenant-token"))),
+        ..Default::default()
+    };
+    client.list("foo", Some(&options)).unwrap();
+    let options = QueryOptions {
+        token: Some(RequestToken::AgentDefault),
+        ..Default::default()
+    };
+    client.list("foo", Some(&options)).unwrap();
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].headers["X-Consul-Token"], "config-token");
+    assert_eq!(requests[1].headers["X-Consul-Token"], "tenant-token");
+    assert!(requests[2].headers.get("X-Consul-Token").is_none());
+}
+
+#[test]
+fn token_override_write_test() {
+    let transport = ScriptedTransport::new(&[(200, "true"), (200, "true")]);
+    let client = client(&transport);
+    let pair = KVPair {
+        Key: String::from("foo"),
+        ..Default::default()
+    };
+
+    let options = WriteOptions {
+        token: Some(RequestToken::Token(String::from("tenant-token"))),
+        ..Default::default()
+    };
+    client.put(&pair, Some(&options)).unwrap();
+    let options = WriteOptions {
+        token: Some(RequestToken::AgentDefault),
+        ..Default::default()
+    };
+    client.delete("foo", Some(&options)).unwrap();
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].headers["X-Consul-Token"], "tenant-token");
+    assert!(requests[1].headers.get("X-Consul-Token").is_none());
+}
@@ -0,0 +1,96 @@
+extern crate consul;
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::Method;
+
+use common::ScriptedTransport;
+use consul::errors::ErrorKind;
+use consul::health::Health;
+use consul::kv::{KVPair, KV};
+use consul::{Client, Config, HttpBasicAuth, RetryPolicy};
+
+fn client(transport: &Arc<ScriptedTransport>) -> Client {
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
+    config.retry = RetryPolicy {
+        initial_backoff: Duration::from_millis(1),
+        ..Default::default()
+    };
+    Client::new(config)
+}
+
+#[test]
+fn transport_query_test() {
+    let transport = ScriptedTransport::new(&[(
+        200,
+        r#"[{"Key":"foo","Value":"YmFy","Flags":0,"CreateIndex":1,"ModifyIndex":42,"LockIndex":0}]"#,
+    )]);
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
+    config.token = Some(String::from("secret"));
+    config.http_auth = Some(HttpBasicAuth {
+        username: String::from("user"),
+        password: Some(String::from("pass")),
+    });
+    let client = Client::new(config);
+
+    let (pair, meta) = client.get("foo", None).unwrap();
+    assert_eq!(pair.unwrap().Value, b"bar");
+    assert_eq!(meta.last_index, Some(42));
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, Method::GET);
+    assert_eq!(requests[0].url.path(), "/v1/kv/foo");
+    assert_eq!(requests[0].headers["X-Consul-Token"], "secret");
+    assert_eq!(requests[0].headers["Authorization"], "Basic dXNlcjpwYXNz");
+    assert!(requests[0].body.is_none());
+}
+
+#[test]
+fn transport_write_test() {
+    let transport = ScriptedTransport::new(&[(200, "true")]);
+    let client = client(&transport);
+    let pair = KVPair {
+        Key: String::from("foo"),
+        Value: b"bar".to_vec(),
+        ..Default::default()
+    };
+    let (written, _) = client.put(&pair, None).unwrap();
+    assert!(written);
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].method, Method::PUT);
+    assert_eq!(requests[0].url.path(), "/v1/kv/foo");
+    assert_eq!(
+        requests[0].headers["Content-Type"],
+        "application/octet-stream"
+    );
+    assert_eq!(requests[0].body.as_deref(), Some(&b"bar"[..]));
+}
+
+#[test]
+fn transport_retry_test() {
+    let transport = ScriptedTransport::new(&[(503, "No cluster leader"), (200, "[]")]);
+    let client = client(&transport);
+    let (entries, meta) = client.service("web", None, true, None).unwrap();
+    assert!(entries.is_empty());
+    assert_eq!(meta.attempts, 2);
+    assert_eq!(transport.requests().len(), 2);
+    let query = transport.requests()[1].url.query().unwrap().to_owned();
+    assert!(query.contains("passing"));
+}
+
+#[test]
+fn transport_error_status_test() {
+    let transport = ScriptedTransport::new(&[(403, "ACL not found")]);
+    let client = client(&transport);
+    let err = client.list("foo", None).unwrap_err();
+    match err.kind() {
+        ErrorKind::PermissionDenied(body) => assert_eq!(body, "ACL not found"),
+        kind => panic!("unexpected error {:?}", kind),
+    }
+}
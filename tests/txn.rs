@@ -0,0 +1,188 @@
+extern crate consul;
+mod common;
+
+use serde_json::Value;
+
+use common::ScriptedTransport;
+use consul::agent::AgentService;
+use consul::errors::ErrorKind;
+use consul::health::{HealthCheck, Node};
+use consul::kv::{KVPair, KV};
+use consul::session::{Session, SessionEntry};
+use consul::test_support::FakeConsul;
+use consul::txn::{CatalogVerb, Transaction, Txn, TxnError, TxnResult};
+use consul::{Client, Config};
+
+fn put(client: &Client, key: &str, value: &[u8]) -> KVPair {
+    let pair = KVPair {
+        Key: key.to_owned(),
+        Value: value.to_vec(),
+        ..Default::default()
+    };
+    client.put(&pair, None).unwrap();
+    client.get(key, None).unwrap().0.unwrap()
+}
+
+#[test]
+fn txn_commit_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let version = put(&client, "features/version", b"1");
+    put(&client, "features/old-ui", b"on");
+
+    let txn = Txn::new()
+        .kv_check_index("features/version", version.ModifyIndex.unwrap())
+        .kv_check_not_exists("features/new-ui")
+        .kv_set("features/new-ui", b"on")
+        .with_flags(7)
+        .kv_cas(&KVPair {
+            Value: b"2".to_vec(),
+            ..version
+        })
+        .kv_delete("features/old-ui")
+        .kv_get_tree("features/");
+    let (results, _) = client.txn(&txn, None).unwrap();
+
+    let keys: Vec<_> = results
+        .iter()
+        .map(|r| match r {
+            TxnResult::KV(pair) => (pair.Key.as_str(), pair.Value.as_slice()),
+            r => panic!("unexpected result {:?}", r),
+        })
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            ("features/version", &b""[..]),
+            ("features/new-ui", b""),
+            ("features/version", b""),
+            ("features/new-ui", b"on"),
+            ("features/version", b"2"),
+        ]
+    );
+    let pair = client.get("features/new-ui", None).unwrap().0.unwrap();
+    assert_eq!(pair.Flags, Some(7));
+    assert!(client.get("features/old-ui", None).unwrap().0.is_none());
+}
+
+#[test]
+fn txn_rollback_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let version = put(&client, "features/version", b"1");
+    put(&client, "features/version", b"2");
+
+    let txn = Txn::new()
+        .kv_set("features/new-ui", b"on")
+        .kv_check_index("features/version", version.ModifyIndex.unwrap())
+        .kv_get("features/missing");
+    let err = client.txn(&txn, None).unwrap_err();
+    match err.kind() {
+        ErrorKind::TxnRolledBack(errors) => {
+            let indexes: Vec<_> = errors.iter().map(|e| e.OpIndex).collect();
+            assert_eq!(indexes, [1, 2]);
+        }
+        kind => panic!("unexpected error {:?}", kind),
+    }
+    assert!(client.get("features/new-ui", None).unwrap().0.is_none());
+}
+
+#[test]
+fn txn_lock_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let (session, _) = client.create(&SessionEntry::default(), None).unwrap();
+    let session = session.ID.unwrap();
+
+    let txn = Txn::new().kv_lock("leader", b"me", &session);
+    client.txn(&txn, None).unwrap();
+    let pair = client.get("leader", None).unwrap().0.unwrap();
+    assert_eq!(pair.Session.as_ref(), Some(&session));
+    assert_eq!(pair.LockIndex, Some(1));
+
+    let txn = Txn::new()
+        .kv_check_session("leader", &session)
+        .kv_unlock("leader", b"", &session);
+    client.txn(&txn, None).unwrap();
+    let pair = client.get("leader", None).unwrap().0.unwrap();
+    assert!(pair.Session.is_none());
+
+    let err = client.txn(&txn, None).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::TxnRolledBack(_)));
+}
+
+#[test]
+fn txn_catalog_test() {
+    let transport = ScriptedTransport::new(&[
+        (
+            200,
+            r#"{"Results":[{"Node":{"Node":"n1","Address":"10.0.0.1","ModifyIndex":5}},
+                {"Service":{"ID":"web","Service":"web","Port":80}},
+                {"Check":{"Node":"n1","CheckID":"web-alive","Status":"passing"}}],"Errors":null}"#,
+        ),
+        (
+            409,
+            r#"{"Results":null,"Errors":[{"OpIndex":1,"What":"failed to delete service"}]}"#,
+        ),
+    ]);
+    let mut config = Config::new().unwrap();
+    config.transport = Some(transport.clone());
+    let client = Client::new(config);
+
+    let node = Node {
+        Node: String::from("n1"),
+        Address: String::from("10.0.0.1"),
+        ModifyIndex: 4,
+        ..Default::default()
+    };
+    let service = AgentService {
+        ID: String::from("web"),
+        Service: String::from("web"),
+        Port: 80,
+        ..Default::default()
+    };
+    let check = HealthCheck {
+        Node: String::from("n1"),
+        CheckID: String::from("web-alive"),
+        Status: String::from("passing"),
+        ..Default::default()
+    };
+    let txn = Txn::new()
+        .node(CatalogVerb::Cas, node)
+        .service(CatalogVerb::Set, "n1", service)
+        .check(CatalogVerb::Set, check);
+    let (results, _) = client.txn(&txn, None).unwrap();
+    assert!(matches!(&results[0], TxnResult::Node(n) if n.ModifyIndex == 5));
+    assert!(matches!(&results[1], TxnResult::Service(s) if s.Port == 80));
+    assert!(matches!(&results[2], TxnResult::Check(c) if c.CheckID == "web-alive"));
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].url.path(), "/v1/txn");
+    let ops: Value = serde_json::from_slice(requests[0].body.as_ref().unwrap()).unwrap();
+    assert_eq!(ops[0]["Node"]["Verb"], "cas");
+    assert_eq!(ops[0]["Node"]["Node"]["ModifyIndex"], 4);
+    assert_eq!(ops[1]["Service"]["Node"], "n1");
+    assert_eq!(ops[1]["Service"]["Service"]["ID"], "web");
+    assert_eq!(ops[2]["Check"]["Check"]["CheckID"], "web-alive");
+
+    let txn = Txn::new().kv_delete_tree("web/").service(
+        CatalogVerb::DeleteCas,
+        "n1",
+        AgentService::default(),
+    );
+    let err = client.txn(&txn, None).unwrap_err();
+    match err.kind() {
+        ErrorKind::TxnRolledBack(errors) => assert_eq!(
+            *errors,
+            [TxnError {
+                OpIndex: 1,
+                What: String::from("failed to delete service"),
+            }]
+        ),
+        kind => panic!("unexpected error {:?}", kind),
+    }
+    assert_eq!(
+        err.to_string(),
+        "Transaction rolled back: operation 1: failed to delete service"
+    );
+}
@@ -0,0 +1,100 @@
+#![cfg(unix)]
+extern crate consul;
+mod common;
+
+use std::os::unix::net::UnixListener;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::serve_unix_once;
+use consul::{Client, Config, QueryOptions, RetryPolicy};
+
+/// Accepts a connection without ever replying, returning the agent address.
+fn serve_unix_silent() -> String {
+    let path = std::env::temp_dir().join(format!(
+        "consul-rust-silent-{}-{:?}.sock",
+        std::process::id(),
+        thread::current().id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_secs(60));
+        drop(stream);
+    });
+    format!("unix://{}", path.display())
+}
+
+fn silent_config() -> Config {
+    let mut config = Config::new().unwrap();
+    config.address = serve_unix_silent();
+    config.retry = RetryPolicy::none();
+    config
+}
+
+/// Blocking query with the shortest timeout, its wait time plus 10 seconds.
+fn blocking_query() -> QueryOptions {
+    QueryOptions {
+        wait_index: Some(1),
+        wait_time: Some(Duration::from_millis(1)),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn unix_kv_get_test() {
+    use consul::kv::KV;
+    let (address, server) = serve_unix_once(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nX-Consul-Index: 42\r\n\
+         X-Note: caf\u{e9}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+         10\r\n[{\"Key\":\"foo\",\"V\r\n\
+         10\r\nalue\":\"YmFy\"}]  \r\n0\r\n\r\n",
+    );
+    let mut config = Config::new().unwrap();
+    config.address = address;
+    let client = Client::new(config);
+
+    let (pair, meta) = client.get("foo", None).unwrap();
+    assert_eq!(pair.unwrap().Value, b"bar");
+    assert_eq!(meta.last_index, Some(42));
+    assert!(server.join().unwrap().starts_with("GET /v1/kv/foo"));
+}
+
+#[test]
+fn unix_session_create_test() {
+    use consul::session::{Session, SessionEntry};
+    let (address, server) = serve_unix_once(
+        "HTTP/1.1 200 OK\r\nContent-Length: 13\r\nConnection: close\r\n\r\n{\"ID\":\"abcd\"}",
+    );
+    let mut config = Config::new().unwrap();
+    config.address = address;
+    let client = Client::new(config);
+
+    let (entry, _) = client.create(&SessionEntry::default(), None).unwrap();
+    assert_eq!(entry.ID.as_deref(), Some("abcd"));
+    assert!(server.join().unwrap().starts_with("PUT /v1/session/create"));
+}
+
+#[test]
+fn unix_timeout_test() {
+    use consul::kv::KV;
+    let start = Instant::now();
+    Client::new(silent_config())
+        .get("foo", Some(&blocking_query()))
+        .unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(30));
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn unix_async_timeout_test() {
+    use consul::asynchronous::kv::KV;
+    let client = consul::asynchronous::Client::new(silent_config());
+    let start = Instant::now();
+    client
+        .get("foo", Some(&blocking_query()))
+        .await
+        .unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(30));
+}
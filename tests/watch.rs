@@ -0,0 +1,112 @@
+extern crate consul;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use consul::errors::{Error, Result};
+use consul::kv::KV;
+use consul::test_support::FakeConsul;
+use consul::watch::{watch, WatchOptions};
+use consul::{Client, QueryMeta};
+
+fn meta(index: u64) -> QueryMeta {
+    QueryMeta {
+        last_index: Some(index),
+        ..Default::default()
+    }
+}
+
+fn options() -> WatchOptions {
+    WatchOptions {
+        min_interval: Duration::from_millis(1),
+        initial_error_backoff: Duration::from_millis(1),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn watch_index_rules_test() {
+    let mut responses: VecDeque<Result<(&str, QueryMeta)>> = vec![
+        Ok(("a", meta(5))),
+        Ok(("a", meta(5))),
+        Ok(("b", meta(7))),
+        Err(Error::from("agent unavailable")),
+        Ok(("c", meta(3))),
+        Ok(("d", meta(0))),
+    ]
+    .into_iter()
+    .collect();
+    let indexes = Rc::new(RefCell::new(Vec::new()));
+    let seen = indexes.clone();
+
+    let mut changes = watch(
+        move |q| {
+            seen.borrow_mut().push(q.wait_index);
+            responses.pop_front().unwrap()
+        },
+        options(),
+    );
+
+    assert_eq!(changes.next().unwrap().unwrap().0, "a");
+    assert_eq!(changes.next().unwrap().unwrap().0, "b");
+    assert!(changes.next().unwrap().is_err());
+    assert_eq!(changes.next().unwrap().unwrap().0, "c");
+    assert_eq!(changes.next().unwrap().unwrap().0, "d");
+    assert_eq!(
+        *indexes.borrow(),
+        vec![None, Some(5), Some(5), Some(7), Some(7), None]
+    );
+}
+
+#[test]
+fn watch_stop_test() {
+    let mut changes = watch(|_| Ok(((), meta(1))), options());
+    let stop = changes.stop_handle();
+    assert!(changes.next().unwrap().is_ok());
+    stop.stop();
+    assert!(changes.next().is_none());
+}
+
+#[test]
+fn watch_stop_wait_time_test() {
+    let waits = Rc::new(RefCell::new(Vec::new()));
+    let seen = waits.clone();
+    let mut changes = watch(
+        move |q| {
+            let mut waits = seen.borrow_mut();
+            waits.push(q.wait_time.unwrap());
+            Ok(((), meta(waits.len() as u64)))
+        },
+        options(),
+    );
+    changes.next().unwrap().unwrap();
+    let stop = changes.stop_handle();
+    changes.next();
+    drop(stop);
+    changes.next();
+    let (wait, stop_wait) = (Duration::from_secs(300), Duration::from_secs(10));
+    assert_eq!(*waits.borrow(), vec![wait, stop_wait, wait]);
+}
+
+#[test]
+fn watch_stop_blocking_query_test() {
+    let consul = FakeConsul::start().unwrap();
+    let client = Client::new(consul.config().unwrap());
+    let options = WatchOptions {
+        stop_wait_time: Duration::from_millis(200),
+        ..options()
+    };
+    let mut changes = watch(move |q| client.get("foo", Some(q)), options);
+    let stop = changes.stop_handle();
+    let handle = thread::spawn(move || {
+        changes.next().unwrap().unwrap();
+        changes.next().is_none()
+    });
+    thread::sleep(Duration::from_millis(100));
+    let start = Instant::now();
+    stop.stop();
+    assert!(handle.join().unwrap());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}